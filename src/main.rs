@@ -1,272 +1,9193 @@
-use anyhow::Result;
-use clap::Parser;
-use reqwest::header::AUTHORIZATION;
-use rust_bert::pipelines::sentiment::{
-    Sentiment, SentimentConfig, SentimentModel, SentimentPolarity,
+use anyhow::{anyhow, Result};
+use chrono::{
+    format::strftime::StrftimeItems, format::Item, DateTime, NaiveDate, NaiveDateTime,
+    SecondsFormat, TimeZone, Timelike, Utc,
+};
+use chrono_tz::Tz;
+use clap::{ArgEnum, ArgGroup, Parser, Subcommand};
+use comfy_table::{presets::UTF8_FULL, Cell, Color, ContentArrangement, Table};
+use flate2::{read::MultiGzDecoder, write::GzEncoder};
+use hmac::{Hmac, Mac};
+use rand::{rngs::StdRng, seq::index, Rng, SeedableRng};
+use regex::{Regex, RegexBuilder};
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, CONTENT_TYPE},
+    StatusCode,
+};
+use rust_bert::{
+    m2m_100::M2M100SourceLanguages,
+    pipelines::{
+        common::ModelType,
+        sentiment::{Sentiment, SentimentConfig, SentimentPolarity},
+        sequence_classification::Label,
+        sequence_classification::SequenceClassificationModel,
+        translation::{Language, TranslationModelBuilder},
+        zero_shot_classification::{ZeroShotClassificationConfig, ZeroShotClassificationModel},
+    },
+    resources::{LocalResource, RemoteResource, Resource},
 };
 use serde::{Deserialize, Serialize};
+use serde_json::{json, ser::PrettyFormatter, Value};
+use sha2::{Digest, Sha256};
 use std::{
-    env,
-    fs::File,
-    io::prelude::*,
-    sync::mpsc,
+    cmp::Ordering,
+    collections::{hash_map::DefaultHasher, BTreeMap, HashMap, HashSet},
+    env, fmt,
+    fs::{File, OpenOptions},
+    hash::{Hash, Hasher},
+    io::{prelude::*, BufReader, BufWriter, IsTerminal},
+    panic::{self, AssertUnwindSafe},
+    sync::{mpsc, Arc, Mutex},
     thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
-use tokio::{sync::oneshot, task};
+use tch::{Cuda, Device};
+use tokio::{sync::oneshot, task, time};
 use url::Url;
 
 static BEARER_ENV_TOKEN_NAME: &str = "HAPPY_TWEET_BEARER_TOKEN";
+static TERM_ENV_NAME: &str = "HAPPY_TWEET_TERM";
+static WEBHOOK_SECRET_ENV_NAME: &str = "HAPPY_TWEET_WEBHOOK_SECRET";
+/// Where rust-bert caches the downloaded models, `~/.cache/.rustbert` by default
+static MODEL_CACHE_ENV_NAME: &str = "RUSTBERT_CACHE";
+static USAGE_FILE_NAME: &str = ".happy-tweet-usage.json";
+static STDOUT_PATH: &str = "/dev/stdout";
+/// File name used when the output is a directory
+static DEFAULT_OUTPUT_NAME: &str = "happy-tweets";
+
+/// Version of the output schema, bumped on every breaking change
+const SCHEMA_VERSION: u32 = 1;
+
+/// Why an output can't be appended to when it is not valid JSON
+static CORRUPTED_REASON: &str = "it isn't valid JSON, maybe a previous run was interrupted while writing it. Use `--recover` to back it up and start a new file";
+
+/// Times a webhook delivery is tried before giving up
+const WEBHOOK_ATTEMPTS: u32 = 3;
+/// Upper bound of the delay before the first retry, doubled on every attempt
+const BACKOFF_BASE: Duration = Duration::from_secs(2);
+/// Most extra seconds waited after the rate limit resets, so runs sharing a
+/// token don't all retry at the same second
+const RATE_LIMIT_JITTER: u64 = 10;
+
+/// Replaces the Twitter API, e.g. by a proxy or a mock server
+static API_URL_ENV_NAME: &str = "HAPPY_TWEET_API_URL";
+static API_URL: &str = "https://api.twitter.com";
+static STREAM_PATH: &str = "/2/tweets/search/stream";
+static STREAM_RULES_PATH: &str = "/2/tweets/search/stream/rules";
+/// Tag of the stream rules this tool adds, so other rules of the app are kept
+static STREAM_RULE_TAG: &str = "happy-tweet";
+/// Milliseconds from the UNIX epoch to the epoch of the tweet ids
+const TWITTER_EPOCH_MILLIS: i64 = 1_288_834_974_657;
+
+/// Tweets fetched above each reply with `--with-context`, and the most
+/// lookups made for them
+const THREAD_DEPTH: usize = 2;
+const THREAD_MAX_REQUESTS: usize = 10;
+/// Reconnections to the stream after which the backoff stops doubling
+const STREAM_MAX_ATTEMPT: u32 = 6;
+
+/// Tweets per Discord or Slack message, Discord allows up to 10 embeds
+const WEBHOOK_BATCH_SIZE: usize = 10;
+/// Characters allowed in a Discord embed description and a Slack text block
+const DISCORD_DESCRIPTION_LIMIT: usize = 4096;
+const SLACK_TEXT_LIMIT: usize = 3000;
+
+/// Wait between the messages of a Discord or Slack webhook
+const WEBHOOK_BATCH_DELAY: Duration = Duration::from_secs(2);
+
+static NOT_FOUND_ERROR: &str = "https://api.twitter.com/2/problems/resource-not-found";
+
+static SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Requests per window allowed by the rate limiter unless `--rate-limit` is
+/// set, the limit of the recent search with an app token
+const DEFAULT_RATE_LIMIT: u32 = 450;
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(15 * 60);
+
+/// Emoji counted by `--emoji-boost` unless `--emoji-table` is set. The
+/// variation selectors are left out so `❤` also matches `❤️`
+const EMOJI_POLARITIES: &[(&str, f64)] = &[
+    ("😊", 1.0),
+    ("😀", 1.0),
+    ("😃", 1.0),
+    ("😄", 1.0),
+    ("😁", 1.0),
+    ("😂", 1.0),
+    ("🥰", 1.0),
+    ("😍", 1.0),
+    ("😎", 1.0),
+    ("🥳", 1.0),
+    ("🎉", 1.0),
+    ("❤", 1.0),
+    ("👍", 1.0),
+    ("🙌", 1.0),
+    ("✨", 1.0),
+    ("😢", -1.0),
+    ("😭", -1.0),
+    ("😞", -1.0),
+    ("😔", -1.0),
+    ("😠", -1.0),
+    ("😡", -1.0),
+    ("🤬", -1.0),
+    ("💔", -1.0),
+    ("👎", -1.0),
+    ("😩", -1.0),
+    ("😒", -1.0),
+];
+
+/// Buckets of the score histogram of `--stats-json`
+const HISTOGRAM_BUCKETS: usize = 10;
+
+/// Characters of a tweet shown in a row of the table format
+const TABLE_CONTENT_LIMIT: usize = 120;
+
+/// Number of hashes of the MinHash signatures used by `--dedup-fuzzy`
+const MINHASH_SIZE: usize = 128;
+
+/// Class of the SST-2 models for positive sentiment
+const DEFAULT_POSITIVE_ID: i64 = 1;
+
+/// Files of the multilingual XLM-RoBERTa model fine-tuned on tweets, as the
+/// cache name and url `RemoteResource` expects
+static MULTILINGUAL_MODEL: (&str, &str) = (
+    "twitter-xlm-roberta-base-sentiment/model",
+    "https://huggingface.co/cardiffnlp/twitter-xlm-roberta-base-sentiment/resolve/main/rust_model.ot",
+);
+static MULTILINGUAL_CONFIG: (&str, &str) = (
+    "twitter-xlm-roberta-base-sentiment/config",
+    "https://huggingface.co/cardiffnlp/twitter-xlm-roberta-base-sentiment/resolve/main/config.json",
+);
+static MULTILINGUAL_VOCAB: (&str, &str) = (
+    "twitter-xlm-roberta-base-sentiment/spiece",
+    "https://huggingface.co/cardiffnlp/twitter-xlm-roberta-base-sentiment/resolve/main/sentencepiece.bpe.model",
+);
+/// Class of the multilingual model for positive sentiment, after negative
+/// and neutral
+const MULTILINGUAL_POSITIVE_ID: i64 = 2;
+
+/// Byte order mark starting the outputs written with `--output-encoding utf8-bom`
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Frames of the spinner telling the phase a search is in
+static SPINNER_FRAMES: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+const SPINNER_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Conventions of the locales `--locale` knows, by language or by language
+/// and region, tried in that order
+const LOCALES: &[(&str, Locale)] = &[
+    ("en_US", Locale::new('.', Some(','), "%m/%d/%Y %H:%M")),
+    ("en", Locale::new('.', Some(','), "%d/%m/%Y %H:%M")),
+    ("de", Locale::new(',', Some('.'), "%d.%m.%Y %H:%M")),
+    ("es", Locale::new(',', Some('.'), "%d/%m/%Y %H:%M")),
+    ("fr", Locale::new(',', Some('\u{202F}'), "%d/%m/%Y %H:%M")),
+    ("it", Locale::new(',', Some('.'), "%d/%m/%Y %H:%M")),
+    ("nl", Locale::new(',', Some('.'), "%d-%m-%Y %H:%M")),
+    ("pt", Locale::new(',', Some('.'), "%d/%m/%Y %H:%M")),
+    ("ja", Locale::new('.', Some(','), "%Y/%m/%d %H:%M")),
+    ("zh", Locale::new('.', Some(','), "%Y/%m/%d %H:%M")),
+];
+
+/// Widest `--indent` in spaces
+const MAX_INDENT: usize = 16;
+
+/// Most requests at a time of `--fetch-concurrency`, and threads of
+/// `--classify-threads`
+const MAX_CONCURRENCY: usize = 64;
+
+/// Seed of torch, set when each model is spawned so the commands without one
+/// don't touch torch. The pipelines run without
+/// dropout, so it only matters to models drawing random numbers while
+/// classifying
+const MODEL_SEED: i64 = 42;
+
+/// Default `--max-content-bytes`, above the longest standard tweet so only
+/// long-form tweets get truncated
+const DEFAULT_MAX_CONTENT_BYTES: usize = 2048;
+
+/// Default `--user-agent`, telling the tool and its version
+const USER_AGENT: &str = concat!("happy-tweet/", env!("CARGO_PKG_VERSION"));
+
+/// Days back the recent search reaches
+const RECENT_SEARCH_DAYS: i64 = 7;
+/// Kept from the start of the recent search so a `--since` right at its limit
+/// is still valid when the request arrives
+const RECENT_SEARCH_MARGIN: chrono::Duration = chrono::Duration::minutes(1);
+/// The recent search needs `end_time` to be at least this long ago
+const END_TIME_MARGIN: chrono::Duration = chrono::Duration::seconds(10);
+
+/// Exit code of a search Twitter found no tweets for, so scheduled jobs can
+/// tell it from a failure
+const NO_RESULTS_EXIT_CODE: i32 = 3;
+
+/// Kinds of tweets `--originals-only` leaves out, with the flag leaving out
+/// each one
+const NON_ORIGINAL_KINDS: [(&str, &str); 3] = [
+    ("retweet", "--no-retweets"),
+    ("reply", "--no-replies"),
+    ("quote", "--no-quotes"),
+];
+
+/// Most recent mentions the mentions timeline reaches
+const MENTIONS_TIMELINE_LIMIT: usize = 800;
+
+/// Longest query accepted by the recent search endpoint
+const MAX_QUERY_LENGTH: usize = 512;
+/// Largest radius of the `point_radius` operator of `--near`
+const MAX_RADIUS_MILES: f64 = 25.0;
+const KM_PER_MILE: f64 = 1.609344;
+
+/// Operators of the recent search endpoint printed by `operators`, with what
+/// they match and an example term
+const SEARCH_OPERATORS: &[(&str, &str, &str)] = &[
+    ("word", "Tweets containing the word", "happy"),
+    (
+        "\"exact phrase\"",
+        "Tweets containing the exact phrase",
+        "\"good morning\"",
+    ),
+    ("#hashtag", "Tweets with the hashtag", "#disney"),
+    ("@user", "Tweets mentioning the user", "@nasa"),
+    (
+        "from:user",
+        "Tweets written by the user",
+        "from:barackobama",
+    ),
+    ("to:user", "Replies to the user", "to:nasa"),
+    (
+        "lang:code",
+        "Tweets Twitter detected in the language",
+        "paella lang:es",
+    ),
+    (
+        "is:retweet",
+        "Retweets, negate it to ignore them",
+        "-is:retweet",
+    ),
+    ("is:reply", "Replies to other tweets", "-is:reply"),
+    ("is:quote", "Quotes of other tweets", "is:quote"),
+    ("has:links", "Tweets with links", "-has:links"),
+    ("has:media", "Tweets with images or videos", "has:media"),
+    (
+        "has:hashtags",
+        "Tweets with at least one hashtag",
+        "has:hashtags",
+    ),
+    (
+        "url:text",
+        "Tweets linking to a url containing the text",
+        "url:github.com",
+    ),
+    (
+        "context:domain.entity",
+        "Tweets annotated with the topic",
+        "context:10.799022225751871488",
+    ),
+    ("a OR b", "Tweets matching either side", "(#dc OR #marvel)"),
+    (
+        "-operator",
+        "Tweets not matching the operator",
+        "eurovision -birthday",
+    ),
+    (
+        "(group)",
+        "Groups operators, e.g. for OR",
+        "(happy OR happiness) lang:en",
+    ),
+];
+
+/// Limits of `max_results` for the recent search endpoint
+const MIN_RESULTS: usize = 10;
+const MAX_RESULTS: usize = 100;
 
 #[derive(Parser)]
 #[clap(
     author = "onmax",
     version,
-    about = "A cli tool for fetching happy tweets given a term"
+    about = "A cli tool for fetching happy tweets given a term",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true,
+    group(ArgGroup::new("random").multiple(true).args(&["sample", "balance"]))
 )]
 struct Arguments {
-    #[clap(forbid_empty_values = true, validator = validate_term_search)]
-    /// The term to search for. You can use Twitter's search features like: '@', 'from', 'to', geography locations, etc. More info: https://github.com/onmax/happy-tweet#advance-search-features
-    term: String,
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    #[clap(required = true, env = TERM_ENV_NAME, forbid_empty_values = true, validator = validate_term_search)]
+    /// The term to search for. You can use Twitter's search features like: '@', 'from', 'to', geography locations, etc. More info: https://github.com/onmax/happy-tweet#advance-search-features. You can also set an env variable named `HAPPY_TWEET_TERM`, the argument takes precedence
+    term: Option<String>,
+
+    #[clap(long)]
+    /// Space-separated words of which tweets must contain at least one, added to the term as `(word1 OR word2)`
+    any: Option<String>,
+
+    #[clap(long)]
+    /// Space-separated words that tweets must all contain, added to the term
+    all: Option<String>,
+
+    #[clap(long, validator = validate_lang)]
+    /// Only search tweets in this language, as the BCP 47 tag Twitter detects, e.g. `es`. Added to the term as `lang:<lang>`
+    lang: Option<String>,
+
+    #[clap(long, allow_hyphen_values = true, validator = validate_near)]
+    /// Only search tweets geotagged around a point, as `longitude,latitude,radius` with the radius in `km` or `mi` up to 25 miles, e.g. `-0.37,39.47,10km`. Added to the term as `point_radius:[longitude latitude radius]`
+    near: Option<String>,
+
+    #[clap(long, conflicts_with = "timeline")]
+    /// Print the search query built from the term and the flags, and exit without any request
+    print_query: bool,
+
+    #[clap(long, arg_enum, conflicts_with_all = &["any", "all", "lang", "no-retweets", "no-replies", "no-quotes", "originals-only"])]
+    /// Read a timeline instead of searching. With `mentions` the term is the username whose mentions are classified
+    timeline: Option<Timeline>,
+
+    #[clap(long)]
+    /// Leave out the retweets, adding `-is:retweet` to the term
+    no_retweets: bool,
+
+    #[clap(long)]
+    /// Leave out the replies, adding `-is:reply` to the term
+    no_replies: bool,
+
+    #[clap(long)]
+    /// Leave out the quote tweets, adding `-is:quote` to the term
+    no_quotes: bool,
+
+    #[clap(long)]
+    /// Only keep original tweets, same as `--no-retweets --no-replies --no-quotes`
+    originals_only: bool,
+
+    #[clap(short, long, default_value = "/dev/stdout", multiple_occurrences = true, forbid_empty_values = true, validator = validate_output_path)]
+    /// The output file path. It will be append the results if it exists avoiding duplicates. Output will have a JSON format unless the path ends with `.md`. It can be repeated to write several files from the same results.
+    output: Vec<std::path::PathBuf>,
+
+    #[clap(short, long, arg_enum, multiple_occurrences = true)]
+    /// Format of the output. When repeated, each format applies to the `--output` in the same position. By default it is guessed from the output extension
+    format: Vec<OutputFormat>,
+
+    #[clap(long, arg_enum, conflicts_with = "raw-passthrough")]
+    /// Compress the output files, adding `.gz` or `.zst` to their names. Outputs ending in `.gz` or `.zst` are always read and written compressed
+    compress: Option<Compression>,
 
-    #[clap(short, long, default_value = "/dev/stdout", forbid_empty_values = true, validator = validate_output_path)]
-    /// The output file path. It will be append the results if it exists avoiding duplicates. Output will have a JSON format.
-    output: std::path::PathBuf,
+    #[clap(long, global = true, arg_enum, default_value = "utf8")]
+    /// Text encoding of the output files. `utf8-bom` starts new files with a byte order mark for spreadsheet apps, `latin1` replaces the characters it can't represent, like emoji, by `?`
+    output_encoding: OutputEncoding,
+
+    #[clap(long, global = true, default_value = "2", validator = parse_indent)]
+    /// Indentation of the JSON outputs, a number of spaces or `tab`
+    indent: String,
+
+    #[clap(long, env = "NO_COLOR")]
+    /// Don't color the `table` format. It is also disabled by the `NO_COLOR` env variable
+    no_color: bool,
 
     #[clap(short, long)]
     /// Bearer token for the twitter api. Read the docs for more info: https://github.com/onmax/happy-tweet#twitter-bearer-token. You can also set an env variable named `HAPPY_TWEET_BEARER_TOKEN`
     token: Option<String>,
-}
 
-#[derive(Debug, Serialize, Deserialize)]
-struct User {
-    username: String,
-    profile_image_url: String,
-}
+    #[clap(long, global = true, multiple_occurrences = true, validator = parse_header)]
+    /// Extra header sent with every request to the Twitter API, as `"Name: Value"`, e.g. for a gateway requiring an API key. It can be repeated
+    header: Vec<String>,
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Tweet {
-    url: String,
-    content: String,
-    created_at: String,
-}
+    #[clap(long, global = true, default_value = USER_AGENT, forbid_empty_values = true)]
+    /// User-Agent sent with every request to Twitter and the webhook
+    user_agent: String,
 
-#[derive(Debug, Serialize, Deserialize)]
-struct HappyTweet {
-    user: User,
-    tweet: Tweet,
-    #[serde(skip_serializing, skip_deserializing)]
-    sentiment: Option<Sentiment>,
-}
+    #[clap(long, global = true, validator = parse_locale)]
+    /// Locale of the numbers and dates of the table and Markdown outputs and the messages, e.g. `de_DE` or `fr`. Defaults to the system locale, and `C` keeps them as written to JSON
+    locale: Option<String>,
 
-impl PartialEq for HappyTweet {
-    fn eq(&self, other: &Self) -> bool {
-        self.tweet.url == other.tweet.url
-    }
-}
+    #[clap(long, validator = validate_date_format)]
+    /// Reformat the `created_at` of each tweet using a strftime-like format, e.g. "%Y-%m-%d %H:%M". Dates are shown in UTC unless `--timezone` is set. By default the RFC3339 date returned by the API is kept as it is.
+    date_format: Option<String>,
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-struct TwitterApiResponseData {
-    text: String,
-    created_at: String,
-    author_id: String,
-    id: String,
-}
+    #[clap(long, validator = validate_timezone)]
+    /// IANA timezone, e.g. "Europe/Madrid", of the `--since` and `--until` dates and of the `created_at` of the output
+    timezone: Option<Tz>,
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-struct TwitterApiResponseUser {
-    id: String,
-    username: String,
-    name: String,
-    profile_image_url: String,
-}
+    #[clap(long)]
+    /// Only search tweets posted from this date, as "YYYY-MM-DD", "YYYY-MM-DD HH:MM[:SS]" in `--timezone` (UTC by default) or RFC3339. The recent search only reaches the last 7 days
+    since: Option<String>,
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-struct TwitterApiResponseIncludes {
-    users: Vec<TwitterApiResponseUser>,
-}
+    #[clap(long)]
+    /// Only search tweets posted before this date, in the same formats as `--since`
+    until: Option<String>,
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-struct TwitterApiResponseMeta {
-    newest_id: String,
-    oldest_id: String,
-    result_count: u16,
-    next_token: String,
-}
+    #[clap(long)]
+    /// Randomly select this number of tweets from the happy tweets found before writing them. Useful to spot-check the results
+    sample: Option<usize>,
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-struct TwitterApiResponse {
-    data: Vec<TwitterApiResponseData>,
-    includes: TwitterApiResponseIncludes,
-    meta: TwitterApiResponseMeta,
-}
+    #[clap(long, arg_enum, conflicts_with_all = &["no-sentiment", "raw-passthrough"])]
+    /// Order of the tweets of the run, newest first by default. `happiest` sorts by the probability of being positive, `trending` by that probability halved every `--half-life` of age
+    sort: Option<SortOrder>,
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = Arguments::parse();
-    println!("Starting...");
-    let url = Url::parse_with_params(
-        "https://api.twitter.com/2/tweets/search/recent",
-        &[
-            ("max_results", "100"),
-            ("query", &args.term),
-            ("tweet.fields", "created_at"),
-            ("expansions", "author_id"),
-            ("user.fields", "profile_image_url"),
-        ],
-    )?;
+    #[clap(long, default_value = "6h", validator = parse_window)]
+    /// Age at which a tweet counts half as much with `--sort trending`, like `30m`, `6h` or `2d`
+    half_life: String,
 
-    let bearer = env::var(BEARER_ENV_TOKEN_NAME).unwrap_or_else(|_| args.token.unwrap_or_else(|| {
-        panic!("You need to provide a bearer token as an argument or set an env variable named `{}`. Read more: https://github.com/onmax/happy-tweet", BEARER_ENV_TOKEN_NAME);
-    }));
+    #[clap(long, requires = "random")]
+    /// Seed for the random selection of `--sample` and `--balance`, so the same selection can be reproduced. A random one is picked and printed otherwise
+    seed: Option<u64>,
 
-    let bearer = if !bearer.starts_with("Bearer ") {
-        format!("Bearer {}", bearer)
-    } else {
-        bearer
-    };
+    #[clap(long, validator = parse_balance)]
+    /// With `--sentiment all`, drop random tweets of the most common sentiment until there are `positive:negative` of each, e.g. `1:1` or `3:2`
+    balance: Option<String>,
 
-    let client = reqwest::Client::builder().build()?;
-    let res = client.get(url).header(AUTHORIZATION, bearer).send().await?;
-    if !res.status().is_success() {
-        Err(String::from(
-            "🙅 No response when requesting tweets. Check your term.",
-        ))?;
-    }
-    let data = res.json::<TwitterApiResponse>().await?;
+    #[clap(long)]
+    /// Replace the output file instead of appending the results to it
+    overwrite: bool,
 
-    // TODO remove duplicates
+    #[clap(long, default_value = "1")]
+    /// Maximum number of pages of up to 100 tweets to request
+    max_pages: usize,
 
-    let tweets_string = data
-        .data
-        .iter()
-        .map(|tweet| tweet.text.to_owned())
-        .collect::<Vec<String>>();
-    let (_handle, classifier) = SentimentClassifier::spawn();
-    let sentiments = classifier.predict(tweets_string).await?;
+    #[clap(long)]
+    /// When a page fails, stop paging but still write the tweets found so far. The failures are listed at the end and in `--stats-json`
+    keep_going: bool,
 
-    // convert data to a vector of Tweets
-    let mut tweets: Vec<HappyTweet> = Vec::new();
-    for (tweet, sentiment) in data.data.iter().zip(sentiments) {
-        let user = data
-            .includes
-            .users
-            .iter()
-            .find(|u| u.id == tweet.author_id)
-            .unwrap();
-        let tweet = HappyTweet {
-            tweet: Tweet {
-                content: tweet.text.clone(),
-                url: format!("https://twitter.com/{}/status/{}", user.username, tweet.id),
-                created_at: tweet.created_at.clone(),
-            },
-            user: User {
-                username: user.username.to_string(),
-                profile_image_url: user.profile_image_url.to_string(),
-            },
-            sentiment: Some(sentiment),
-        };
-        tweets.push(tweet);
-    }
+    #[clap(long, conflicts_with = "keep-going")]
+    /// Stop the whole run when a page fails, without writing anything. This is the default
+    fail_fast: bool,
 
-    // Filter tweets to only keep the "Positive" ones
-    let mut tweets = tweets
-        .into_iter()
-        .filter(|tweet| {
-            if let Some(sentiment) = &tweet.sentiment {
-                sentiment.polarity == SentimentPolarity::Positive
-            } else {
-                false
-            }
-        })
-        .collect::<Vec<HappyTweet>>();
+    #[clap(long, global = true, default_value_t = DEFAULT_RATE_LIMIT)]
+    /// Requests per 15 minutes allowed to the Twitter API. It is lowered to the limit Twitter reports. Lower it when other tools share the token
+    rate_limit: u32,
 
-    // check if files exists and appends to the array tweets
-    let output_path = args.output.as_path();
-    if output_path.exists() {
-        let mut file = File::open(output_path)?;
-        let mut contents = String::new();
-        file.read_to_string(&mut contents)?;
-        let existing_tweets: Vec<HappyTweet> = serde_json::from_str(&contents)?;
-        for existing_tweet in existing_tweets {
-            if !tweets.contains(&existing_tweet) {
-                tweets.push(existing_tweet);
-            }
-        }
-    }
+    #[clap(long, global = true, default_value = "0")]
+    /// Milliseconds to wait at least between two requests to the Twitter API, on top of `--rate-limit`, to stay well under the limits of a shared token
+    min_interval: u64,
 
-    // write results
-    let mut file = File::create(output_path)?;
-    let json = serde_json::to_string_pretty(&tweets)?;
-    file.write_all(json.as_bytes())?;
+    #[clap(long, default_value = "1", validator = validate_concurrency)]
+    /// Requests to the Twitter API sent at the same time by the lookups of `--with-context` and `--author-badges`, still within `--rate-limit`. The pages of the search follow each other so they are always fetched one at a time
+    fetch_concurrency: usize,
 
-    println!(
-        "\n\n✅  Finish! Retrieved {} tweets. Check {}",
-        tweets.len(),
-        output_path.display()
-    );
+    #[clap(long, global = true, validator = parse_window)]
+    /// Longest time the run may spend waiting to retry, summed over the rate limit waits and the webhook retries, like `10m`. The run gives up once it would go over
+    retry_budget: Option<String>,
 
-    Ok(())
-}
+    #[clap(long)]
+    /// Write every page of search results as Twitter sent it to this NDJSON file, one page per line with its url and status, to report parsing failures. The file is replaced on every run
+    dump_raw: Option<std::path::PathBuf>,
 
-fn validate_term_search(name: &str) -> Result<(), String> {
-    if name.is_empty() {
-        Err(String::from("The term cannot be empty"))
-    } else if name.trim().len() != name.len() {
-        Err(String::from(
-            "search term name cannot have leading and trailing space",
-        ))
-    } else {
-        Ok(())
-    }
-}
+    #[clap(long)]
+    /// Write a JSON summary of the run to this file: counts, histogram of the scores, duration and API calls
+    stats_json: Option<std::path::PathBuf>,
 
-fn validate_output_path(output: &str) -> Result<(), String> {
-    if output.trim().len() != output.len() {
-        Err(String::from(
-            "output cannot have leading and trailing space",
-        ))
-    } else {
-        Ok(())
-    }
-}
+    #[clap(long, conflicts_with = "overwrite")]
+    /// When the output is not valid JSON, e.g. because a previous run was interrupted while writing it, move it to `<output>.corrupted-<date>` and start a new file instead of stopping
+    recover: bool,
 
-/// Message type for internal channel, passing around texts and return value
-/// senders
-type Message = (Vec<String>, oneshot::Sender<Vec<Sentiment>>);
+    #[clap(long)]
+    /// When an output can't be written, e.g. because the disk is full, print the tweets to the standard output as NDJSON instead of saving them to a file of the temporary directory
+    stdout_on_write_error: bool,
 
-/// Runner for sentiment classification
-#[derive(Debug, Clone)]
-pub struct SentimentClassifier {
-    sender: mpsc::SyncSender<Message>,
-}
+    #[clap(long)]
+    /// Exit with an error when fewer tweets are kept, once the output is written. Useful to get alerted by scheduled jobs
+    expect_min: Option<usize>,
 
-impl SentimentClassifier {
-    /// Spawn a classifier on a separate thread and return a classifier instance
-    /// to interact with it
-    pub fn spawn() -> (JoinHandle<Result<()>>, SentimentClassifier) {
-        let (sender, receiver) = mpsc::sync_channel(100);
-        let handle = thread::spawn(move || Self::runner(receiver));
-        (handle, SentimentClassifier { sender })
-    }
+    #[clap(long)]
+    /// Exit with an error when more tweets are kept, once the output is written
+    expect_max: Option<usize>,
 
-    /// The classification runner itself
-    fn runner(receiver: mpsc::Receiver<Message>) -> Result<()> {
-        // Needs to be in sync runtime, async doesn't work
-        let model = SentimentModel::new(SentimentConfig::default())?;
+    #[clap(long, conflicts_with = "sample")]
+    /// Maximum number of tweets to write, the newest ones passing the filters are kept
+    max_total: Option<usize>,
 
-        while let Ok((texts, sender)) = receiver.recv() {
-            let texts: Vec<&str> = texts.iter().map(String::as_str).collect();
-            let sentiments = model.predict(texts);
-            sender.send(sentiments).expect("sending results");
-        }
+    #[clap(long, requires = "max-total")]
+    /// Classify the tweets of every page as they arrive and stop searching once `--max-total` tweets pass the filters. The size of the pages follows the share of tweets kept so far, so fewer tweets are pulled from the API
+    adaptive_paging: bool,
 
-        Ok(())
-    }
+    #[clap(long)]
+    /// Monthly cap of tweets pulled from the Twitter API. The search stops once it is reached. The number of tweets pulled this month is kept in the usage file
+    quota: Option<usize>,
 
-    /// Make the runner predict a sample and return the result
-    pub async fn predict(&self, texts: Vec<String>) -> Result<Vec<Sentiment>> {
-        let (sender, receiver) = oneshot::channel();
-        task::block_in_place(|| self.sender.send((texts, sender)))?;
-        Ok(receiver.await?)
+    #[clap(long)]
+    /// Path of the file keeping the number of tweets pulled this month. Defaults to `~/.happy-tweet-usage.json`
+    usage_file: Option<std::path::PathBuf>,
+
+    #[clap(long)]
+    /// Seconds to wait for the model to classify a batch of 100 tweets before failing. Bigger batches wait proportionally longer. By default it waits forever
+    classify_batch_timeout: Option<u64>,
+
+    #[clap(long, conflicts_with = "no-sentiment")]
+    /// File caching the sentiment of every text classified, so later runs only classify new tweets. Changing the model starts a new cache
+    sentiment_cache: Option<std::path::PathBuf>,
+
+    #[clap(long, arg_enum, default_value = "positive")]
+    /// Sentiment of the tweets to keep
+    sentiment: SentimentFilter,
+
+    #[clap(long)]
+    /// Write positive and negative tweets to separate files next to the output, e.g. `tweets.positive.json` and `tweets.negative.json` for `tweets.json`. Use it with `--sentiment all`
+    split_output: bool,
+
+    #[clap(long, default_value = "100")]
+    /// Number of batches that can wait to be classified. A bigger buffer keeps more tweets in memory but lets the search go on while the model is busy
+    channel_buffer: usize,
+
+    #[clap(long, default_value_t = DEFAULT_MAX_CONTENT_BYTES, validator = validate_bytes)]
+    /// Longest text given to the model, in bytes. Longer tweets, like the long-form ones of paid accounts, are classified by their beginning to bound the time and memory of the model. The output keeps their whole content
+    max_content_bytes: usize,
+
+    #[clap(long)]
+    /// Classify the tweets longer than `--max-content-bytes` in overlapping windows of that size instead of by their beginning, aggregated with `--window-aggregate`
+    window: bool,
+
+    #[clap(long, default_value = "1024", validator = validate_bytes)]
+    /// Bytes between the starts of consecutive windows of `--window`, at most `--max-content-bytes` so no text is skipped
+    window_stride: usize,
+
+    #[clap(long, arg_enum, default_value = "mean")]
+    /// How the windows of `--window` are combined into the score of the tweet
+    window_aggregate: WindowAggregate,
+
+    #[clap(long)]
+    /// Collapse the runs of whitespace and newlines of the tweets into single spaces and trim them, before they are classified and written
+    normalize_content: bool,
+
+    #[clap(long, requires = "normalize-content")]
+    /// Also strip the zero-width characters of `--normalize-content`, except the joiners inside emoji
+    strip_zero_width: bool,
+
+    #[clap(long, requires = "normalize-content")]
+    /// Only normalize the text given to the model, and write the content of the tweets as Twitter returned it
+    raw_content: bool,
+
+    #[clap(long)]
+    /// Don't show the spinner telling the phase being run. It is never shown when the standard error isn't a terminal
+    quiet: bool,
+
+    #[clap(long, default_value = "100")]
+    /// Number of tweets written to NDJSON outputs between flushes to disk. `0` only flushes once all the tweets are written
+    flush_every: usize,
+
+    #[clap(long)]
+    /// Wrap the tweets in an object with the schema version, the term and the date of the run instead of writing a bare array
+    envelope: bool,
+
+    #[clap(long)]
+    /// CSV file where the number of positive and negative tweets of the day is recorded. Read it back with the `trend` subcommand
+    trend: Option<std::path::PathBuf>,
+
+    #[clap(long, default_value = "0")]
+    /// Drop tweets with fewer different words, leaving mentions, urls and emoji out, like "🎉🎉🎉 @friend". Applied before the classification
+    min_unique_words: usize,
+
+    #[clap(long)]
+    /// Drop the tweets flagged as possibly sensitive. The flag is set by the authors and is imperfect. Applied before the classification
+    no_sensitive: bool,
+
+    #[clap(long, conflicts_with = "only-authors")]
+    /// Drop the tweets of these authors, like the brand's own account or known bots, as comma-separated usernames or the path of a file with one per line. Compared case-insensitively and applied before the classification
+    exclude_authors: Option<String>,
+
+    #[clap(long)]
+    /// Only keep the tweets of these authors, given like `--exclude-authors`
+    only_authors: Option<String>,
+
+    #[clap(long)]
+    /// Drop the tweets of authors who kept the default profile picture, common among throwaway and spam accounts. Applied before the classification
+    require_avatar: bool,
+
+    #[clap(long)]
+    /// Drop the tweets of authors with an empty bio, common among throwaway and spam accounts. Applied before the classification
+    require_bio: bool,
+
+    #[clap(long, validator = parse_hours)]
+    /// Only keep the tweets posted between these hours of the day in `--timezone` (UTC by default), from the first hour included to the last excluded, e.g. `9-17`. Ranges like `22-2` go past midnight. Applied before the classification
+    hours: Option<String>,
+
+    #[clap(long, conflicts_with = "raw-passthrough")]
+    /// Drop tweets with the same content as one written to the output by a previous run, even with another url. The fingerprints of the content are kept in `<output>.fingerprints`
+    compact_dedup: bool,
+
+    #[clap(long, requires = "compact-dedup", validator = parse_window)]
+    /// Only keep the fingerprints of tweets posted within this window, like `12h`, `7d` or `2w`, so the fingerprints file stops growing. Older content can be written again
+    dedup_window: Option<String>,
+
+    #[clap(long, validator = validate_threshold)]
+    /// Drop tweets whose content is at least this similar (from 0 to 1) to an earlier tweet, catching reworded copypasta. Compares every pair of tweets, so it gets slow with many thousands of tweets
+    dedup_fuzzy: Option<f64>,
+
+    #[clap(long, conflicts_with_all = &["overwrite", "fields", "raw-passthrough", "split-output"])]
+    /// Continue the crawl of an NDJSON output stopped by `--max-pages`, the quota or a failure. The tweets already in it are skipped before the classification, and the search continues from the pagination token kept on its last line
+    resume: bool,
+
+    #[clap(long, conflicts_with_all = &["since", "resume"])]
+    /// Only search the tweets posted since the previous run ended, as recorded in `--state-file`. The state is updated once the run succeeds
+    since_last_run: bool,
+
+    #[clap(long)]
+    /// JSON file recording where the run ended, for `--since-last-run`. Defaults to the first output with the `.state.json` extension, e.g. `banana.state.json`
+    state_file: Option<std::path::PathBuf>,
+
+    #[clap(long)]
+    /// Replace the retweets by the tweets they retweet, keeping each original once. Retweets whose original is not returned by Twitter are kept
+    collapse_retweets: bool,
+
+    #[clap(long)]
+    /// Also fetch the bio and location of the authors. Empty ones are left out of the output
+    author_details: bool,
+
+    #[clap(long)]
+    /// Also fetch the verified badge and profile banner of the authors. The banners come from the v1.1 API, which needs elevated access, and are left out when it's denied
+    author_badges: bool,
+
+    #[clap(long)]
+    /// Also fetch the app the tweets were posted with, e.g. "Twitter for iPhone". Twitter leaves it empty for most tweets, and empty ones are left out of the output
+    include_source_app: bool,
+
+    #[clap(long, conflicts_with = "raw-passthrough")]
+    /// For each kept reply, also fetch up to 2 of the tweets it answers, written oldest first as its `thread`
+    with_context: bool,
+
+    #[clap(
+        long,
+        arg_enum,
+        requires = "with-context",
+        conflicts_with = "no-sentiment"
+    )]
+    /// Also classify the `thread` of the replies and combine it with the reply into a `conversation_score`, from 0 to 1
+    conversation_agg: Option<ConversationAggregate>,
+
+    #[clap(long, conflicts_with_all = &["sentiment", "split-output", "trend"])]
+    /// Skip the sentiment analysis and write every tweet found. The model is not even downloaded
+    no_sentiment: bool,
+
+    #[clap(long, global = true, validator = validate_concurrency)]
+    /// Threads the models run on, the number of cores by default. Fewer threads leave cores to other jobs, at the cost of a slower classification
+    classify_threads: Option<usize>,
+
+    #[clap(long, global = true, arg_enum, default_value = "auto")]
+    /// Device to run the sentiment model on. `auto` uses the GPU when CUDA is available
+    device: ModelDevice,
+
+    #[clap(long, validator = validate_emoji_boost, conflicts_with = "no-sentiment")]
+    /// Raise the happiness score (from 0 to 1) of tweets with more happy than sad emoji by this much, and lower it for the opposite. The tweets adjusted are marked with `emoji_boost`
+    emoji_boost: Option<f64>,
+
+    #[clap(long, global = true)]
+    /// Remove the emoji from the text given to the model, for models that handle them poorly. The output keeps them, and `--emoji-boost` still counts them
+    strip_emoji: bool,
+
+    #[clap(long, requires = "emoji-boost")]
+    /// JSON object of the emoji to look for and their polarity, e.g. `{"😊": 1, "😢": -1}`, replacing the built-in ones
+    emoji_table: Option<std::path::PathBuf>,
+
+    #[clap(long, conflicts_with = "no-sentiment")]
+    /// Tag each tweet with a `mood` from its score: ecstatic, happy, content, neutral, glum, sad or miserable
+    mood: bool,
+
+    #[clap(long, default_value = "0.95,0.8,0.6", validator = parse_mood_thresholds)]
+    /// Lowest happiness score of the ecstatic, happy and content moods. Glum, sad and miserable mirror them for negative tweets
+    mood_thresholds: String,
+
+    #[clap(long, conflicts_with_all = &["no-sentiment", "model", "model-path"])]
+    /// Translate the tweets Twitter didn't detect as English before classifying them, written as `translation` next to the original content. The translation model is a download of about 2GB
+    translate: bool,
+
+    #[clap(long, forbid_empty_values = true)]
+    /// Only keep the tweets about this topic, like "customer support", scored by a zero-shot model after the sentiment filter. The score is written as `topic_score`. The model is a download of about 1.6GB
+    topic: Option<String>,
+
+    #[clap(long, default_value = "0.5", validator = validate_threshold, requires = "topic")]
+    /// Probability of being about `--topic` the tweets must reach, from 0 to 1
+    topic_threshold: f64,
+
+    #[clap(long, multiple_occurrences = true)]
+    /// Only keep tweets containing this keyword, case-insensitive. It can be repeated and all of them must appear. Applied before the classification
+    must_contain: Vec<String>,
+
+    #[clap(long, multiple_occurrences = true)]
+    /// Only keep tweets containing at least one of these keywords, case-insensitive. It can be repeated. Applied before the classification
+    any_of: Vec<String>,
+
+    #[clap(long)]
+    /// Treat `--must-contain` and `--any-of` as regular expressions
+    match_regex: bool,
+
+    #[clap(long, multiple_occurrences = true)]
+    /// Only keep tweets tagged with this hashtag, with or without `#`. It can be repeated and any of them is enough. Added to the term as `#tag`, and checked again against the tags of the tweets, as the word alone in the text doesn't match
+    hashtag: Vec<String>,
+
+    #[clap(long, multiple_occurrences = true)]
+    /// Drop tweets tagged with this hashtag, with or without `#`. It can be repeated
+    exclude_hashtag: Vec<String>,
+
+    #[clap(long, multiple_occurrences = true)]
+    /// Only keep tweets Twitter annotated with this topic, the name of a domain like "Brand" or of an entity like "Nintendo", case-insensitive. It can be repeated and any of them is enough
+    context: Vec<String>,
+
+    #[clap(
+        long,
+        arg_enum,
+        use_value_delimiter = true,
+        conflicts_with = "raw-passthrough"
+    )]
+    /// Only write these fields of the tweets, separated by commas, e.g. `username,url,score`. The output can't be read back as tweets, so it is always replaced
+    fields: Vec<Field>,
+
+    #[clap(long, conflicts_with_all = &["envelope", "split-output", "format"])]
+    /// Write the kept tweets with the schema of the Twitter API (`data`, `includes` and `meta`) instead of reshaping them
+    raw_passthrough: bool,
+
+    #[clap(long, conflicts_with = "fields", forbid_empty_values = true)]
+    /// Also give the kept tweets to this shell command on its standard input, one JSON per line, e.g. `jq .tweet.url`. With `--raw-passthrough` it gets a single line with the Twitter schema. The default `/dev/stdout` output is left out so only the command writes there. The run fails when the command does
+    pipe: Option<String>,
+
+    #[clap(long, global = true, env = MODEL_CACHE_ENV_NAME)]
+    /// Directory where the models are downloaded and cached, e.g. to mount a prewarmed cache in CI. You can also set an env variable named `RUSTBERT_CACHE`
+    cache_dir: Option<std::path::PathBuf>,
+
+    #[clap(
+        long,
+        global = true,
+        arg_enum,
+        default_value = "english",
+        conflicts_with = "model-path"
+    )]
+    /// Sentiment model to use. `multilingual` handles tweets in any language but is a bigger download and needs more memory
+    model: ModelPreset,
+
+    #[clap(long)]
+    /// Also POST the kept tweets as JSON to this url, wrapped in an envelope like `--envelope`
+    webhook: Option<Url>,
+
+    #[clap(long, env = WEBHOOK_SECRET_ENV_NAME, hide_env_values = true)]
+    /// Secret to sign the webhook body with, sent as `X-Signature: sha256=<hex HMAC>`. You can also set an env variable named `HAPPY_TWEET_WEBHOOK_SECRET`
+    webhook_secret: Option<String>,
+
+    #[clap(long, arg_enum, default_value = "json", requires = "webhook")]
+    /// Shape of the webhook body. `discord` and `slack` post the tweets as chat messages of up to 10 tweets
+    webhook_format: WebhookFormat,
+
+    #[clap(long, global = true)]
+    /// Directory of a custom sequence classification model with `rust_model.ot`, `config.json` and `vocab.txt` (or `vocab.json` and `merges.txt`). By default the English DistilBERT model fine-tuned on SST-2 is used
+    model_path: Option<std::path::PathBuf>,
+
+    #[clap(
+        long,
+        global = true,
+        arg_enum,
+        default_value = "distilbert",
+        requires = "model-path"
+    )]
+    /// Architecture of the custom model
+    model_type: CustomModelType,
+
+    #[clap(long, global = true, requires = "model-path")]
+    /// Lower case the texts before classifying them, needed by uncased custom models
+    model_lower_case: bool,
+
+    #[clap(long, global = true, requires = "model-path")]
+    /// Class of the custom model kept as positive, by name or index as in the `id2label` of its `config.json`. Every other class is negative. Defaults to `1`, like SST-2 models
+    positive_label: Option<String>,
+
+    #[clap(long, requires = "model-path")]
+    /// Classify the tweets with both the default model and the `--model-path` one, and only consider positive the tweets both find positive with at least `--ensemble-threshold`. Trades recall for fewer sarcastic tweets slipping through
+    ensemble: bool,
+
+    #[clap(long, default_value = "0.5", validator = validate_threshold)]
+    /// Probability of being positive both models of `--ensemble` must reach
+    ensemble_threshold: f64,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Remove the tweets of an output file that have been deleted from Twitter
+    Prune {
+        #[clap(forbid_empty_values = true, validator = validate_output_path)]
+        /// The output file to prune
+        file: std::path::PathBuf,
+
+        #[clap(short, long)]
+        /// Bearer token for the twitter api. You can also set an env variable named `HAPPY_TWEET_BEARER_TOKEN`
+        token: Option<String>,
+    },
+    /// Print the positive ratio over time recorded with `--trend`
+    Trend {
+        #[clap(forbid_empty_values = true)]
+        /// The trend file written with `--trend`
+        file: std::path::PathBuf,
+
+        #[clap(long)]
+        /// Only show the rows of this term
+        term: Option<String>,
+    },
+    /// Combine several output files into one without duplicates, from the newest to the oldest tweet
+    Merge {
+        #[clap(required = true, min_values = 2, forbid_empty_values = true)]
+        /// The output files to merge, in JSON (array or envelope) or NDJSON
+        files: Vec<std::path::PathBuf>,
+
+        #[clap(short, long, forbid_empty_values = true, validator = validate_output_path)]
+        /// File to write the merged tweets to. The tweets it already has are kept unless `--overwrite` is set
+        output: std::path::PathBuf,
+
+        #[clap(short, long, arg_enum)]
+        /// Format of the output. By default it is guessed from the output extension
+        format: Option<OutputFormat>,
+
+        #[clap(long, validator = validate_threshold)]
+        /// Also drop tweets whose content is at least this similar (from 0 to 1) to an earlier tweet
+        dedup_fuzzy: Option<f64>,
+
+        #[clap(long)]
+        /// Replace the output instead of appending to it
+        overwrite: bool,
+    },
+    /// Follow the tweets matching the term as they are posted and append the ones with the sentiment to the output
+    Stream {
+        #[clap(forbid_empty_values = true)]
+        /// The term to follow, with the same operators as a search
+        term: String,
+
+        #[clap(short, long, default_value = "/dev/stdout", forbid_empty_values = true, validator = validate_output_path)]
+        /// File to append the tweets to
+        output: std::path::PathBuf,
+
+        #[clap(short, long, arg_enum)]
+        /// Format of the output. By default it is guessed from the output extension, or NDJSON. Only NDJSON can be appended to as the tweets come
+        format: Option<OutputFormat>,
+
+        #[clap(long, arg_enum, default_value = "positive")]
+        /// Sentiment of the tweets to keep
+        sentiment: SentimentFilter,
+
+        #[clap(long, forbid_empty_values = true)]
+        /// Also give every tweet to this shell command on its standard input as soon as it is classified, one JSON per line. The stream stops once the command exits
+        pipe: Option<String>,
+
+        #[clap(short, long)]
+        /// Bearer token for the twitter api. You can also set an env variable named `HAPPY_TWEET_BEARER_TOKEN`
+        token: Option<String>,
+    },
+    /// Print the first or last tweets of an output file as a table
+    Peek {
+        #[clap(forbid_empty_values = true)]
+        /// The output file to read, in JSON (array or envelope) or NDJSON
+        file: std::path::PathBuf,
+
+        #[clap(long, default_value_t = 10, conflicts_with = "tail")]
+        /// Number of tweets to print from the start of the file
+        head: usize,
+
+        #[clap(long)]
+        /// Print this number of tweets from the end of the file instead
+        tail: Option<usize>,
+
+        #[clap(long, env = "NO_COLOR")]
+        /// Don't color the table. It is also disabled by the `NO_COLOR` env variable
+        no_color: bool,
+    },
+    /// List the Twitter search operators the term can use and the flags that add them
+    Operators,
+    /// Classify a few sentences and print their sentiment, to check a model (e.g. one of `--model-path`) before a crawl, without any request
+    Try {
+        /// Sentences to classify. They are read from the standard input, one per line, when none is given
+        sentences: Vec<String>,
+        #[clap(long)]
+        /// Don't color the scores
+        no_color: bool,
+    },
+    /// Run the searches of a jobs file one after the other, loading each model once, and summarize them
+    Batch {
+        #[clap(forbid_empty_values = true)]
+        /// The jobs file: a search per line, with its term and flags as on the command line, or a JSON array of jobs with a `term` and optionally an `output`, a `lang` and the rest of the `args`
+        file: std::path::PathBuf,
+
+        #[clap(long)]
+        /// Also write the summary of the jobs to this JSON file
+        summary: Option<std::path::PathBuf>,
+    },
+    /// Print the distribution of the scores of an output written with `--fields` including `score`, without any request or model
+    Analyze {
+        #[clap(forbid_empty_values = true)]
+        /// The output file to analyze, in JSON (array or envelope) or NDJSON
+        file: std::path::PathBuf,
+    },
+    /// Check every tweet of an output file against the schema of the outputs and report the malformed ones, without any request or model
+    Validate {
+        #[clap(forbid_empty_values = true)]
+        /// The output file to check, in JSON (array or envelope) or NDJSON
+        file: std::path::PathBuf,
+        #[clap(long, validator = validate_date_format)]
+        /// The `--date-format` the output was written with, its dates are expected in RFC 3339 otherwise
+        date_format: Option<String>,
+    },
+}
+
+/// Field of a tweet that `--fields` can keep
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq)]
+enum Field {
+    Username,
+    ProfileImageUrl,
+    Description,
+    Location,
+    VerifiedType,
+    ProfileBannerUrl,
+    Url,
+    Content,
+    CreatedAt,
+    Hashtags,
+    Context,
+    Translation,
+    EmojiBoost,
+    Source,
+    Mood,
+    MatchedTerms,
+    TopicScore,
+    ConversationScore,
+    /// Probability of the tweet being positive, from 0 to 1
+    Score,
+    Sentiment,
+}
+
+impl Field {
+    /// Key of the field in the JSON outputs, like in the full tweets
+    fn key(self) -> &'static str {
+        match self {
+            Field::Username => "username",
+            Field::ProfileImageUrl => "profile_image_url",
+            Field::Description => "description",
+            Field::Location => "location",
+            Field::VerifiedType => "verified_type",
+            Field::ProfileBannerUrl => "profile_banner_url",
+            Field::Url => "url",
+            Field::Content => "content",
+            Field::CreatedAt => "created_at",
+            Field::Hashtags => "hashtags",
+            Field::Context => "context",
+            Field::Translation => "translation",
+            Field::EmojiBoost => "emoji_boost",
+            Field::Source => "source",
+            Field::Mood => "mood",
+            Field::MatchedTerms => "matched_terms",
+            Field::TopicScore => "topic_score",
+            Field::ConversationScore => "conversation_score",
+            Field::Score => "score",
+            Field::Sentiment => "sentiment",
+        }
+    }
+
+    fn value(self, tweet: &HappyTweet) -> Value {
+        match self {
+            Field::Username => json!(tweet.user.username),
+            Field::ProfileImageUrl => json!(tweet.user.profile_image_url),
+            Field::Description => json!(tweet.user.description),
+            Field::Location => json!(tweet.user.location),
+            Field::VerifiedType => json!(tweet.user.verified_type),
+            Field::ProfileBannerUrl => json!(tweet.user.profile_banner_url),
+            Field::Url => json!(tweet.tweet.url),
+            Field::Content => json!(tweet.tweet.content),
+            Field::CreatedAt => json!(tweet.tweet.created_at),
+            Field::Hashtags => json!(tweet.tweet.hashtags),
+            Field::Context => json!(tweet.tweet.context),
+            Field::Translation => json!(tweet.tweet.translation),
+            Field::EmojiBoost => json!(tweet.tweet.emoji_boost),
+            Field::Source => json!(tweet.tweet.source),
+            Field::Mood => json!(tweet.tweet.mood),
+            Field::MatchedTerms => json!(tweet.tweet.matched_terms),
+            Field::TopicScore => json!(tweet.tweet.topic_score),
+            Field::ConversationScore => json!(tweet.tweet.conversation_score),
+            Field::Score => json!(tweet.sentiment.as_ref().map(happiness)),
+            Field::Sentiment => json!(tweet.sentiment.as_ref().map(|sentiment| {
+                match sentiment.polarity {
+                    SentimentPolarity::Positive => "positive",
+                    SentimentPolarity::Negative => "negative",
+                }
+            })),
+        }
+    }
+
+    /// Value of the field as the text of a table cell, in the `locale`
+    fn text(self, tweet: &HappyTweet, locale: Locale) -> String {
+        match self.value(tweet) {
+            Value::Null => String::new(),
+            Value::String(text) if self == Field::CreatedAt => locale.date(&text),
+            Value::String(text) => text,
+            Value::Array(values) => values
+                .iter()
+                .filter_map(Value::as_str)
+                .collect::<Vec<&str>>()
+                .join(", "),
+            Value::Number(number) => match number.as_f64() {
+                Some(number) => locale.decimal(number),
+                None => number.to_string(),
+            },
+            value => value.to_string(),
+        }
+    }
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq)]
+enum OutputFormat {
+    Json,
+    Markdown,
+    /// One JSON tweet per line
+    Ndjson,
+    /// Aligned table for the terminal, only written to stdout
+    Table,
+}
+
+impl OutputFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Json => "json",
+            OutputFormat::Markdown => "md",
+            OutputFormat::Ndjson => "ndjson",
+            OutputFormat::Table => "txt",
+        }
+    }
+
+    /// Guesses the format from the extension of `path`, before the one of
+    /// the compression if any
+    fn from_path(path: &std::path::Path) -> Option<OutputFormat> {
+        let path = match Compression::from_path(path) {
+            Some(_) => std::path::Path::new(path.file_stem()?),
+            None => path,
+        };
+        match path.extension()?.to_str()?.to_lowercase().as_str() {
+            "json" => Some(OutputFormat::Json),
+            "md" | "markdown" => Some(OutputFormat::Markdown),
+            "ndjson" | "jsonl" => Some(OutputFormat::Ndjson),
+            _ => None,
+        }
+    }
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq)]
+enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    fn extension(self) -> &'static str {
+        match self {
+            Compression::Gzip => "gz",
+            Compression::Zstd => "zst",
+        }
+    }
+
+    /// Guesses the compression from the extension of `path`
+    fn from_path(path: &std::path::Path) -> Option<Compression> {
+        match path.extension()?.to_str()?.to_lowercase().as_str() {
+            "gz" => Some(Compression::Gzip),
+            "zst" => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq)]
+enum OutputEncoding {
+    Utf8,
+    /// UTF-8 starting with a byte order mark, which spreadsheet apps need to
+    /// tell it is UTF-8
+    #[clap(name = "utf8-bom")]
+    Utf8Bom,
+    /// ISO-8859-1, characters out of it are written as `?`
+    Latin1,
+}
+
+impl OutputEncoding {
+    /// Guesses the encoding an existing output was written with. It isn't
+    /// recorded, but Latin-1 text is almost never valid UTF-8
+    fn detect(path: &std::path::Path) -> std::io::Result<OutputEncoding> {
+        let bytes = read_output_bytes(path)?;
+        Ok(if bytes.starts_with(UTF8_BOM) {
+            OutputEncoding::Utf8Bom
+        } else if std::str::from_utf8(&bytes).is_ok() {
+            OutputEncoding::Utf8
+        } else {
+            OutputEncoding::Latin1
+        })
+    }
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq)]
+enum WebhookFormat {
+    Json,
+    Discord,
+    Slack,
+}
+
+impl WebhookFormat {
+    /// Bodies to post for the tweets. JSON posts all of them in one envelope,
+    /// chat messages are batched to stay within the platform limits
+    fn payloads(self, term: &str, tweets: &[HappyTweet]) -> serde_json::Result<Vec<Value>> {
+        if self == WebhookFormat::Json {
+            let envelope = Envelope {
+                schema_version: SCHEMA_VERSION,
+                term: term.to_string(),
+                generated_at: Utc::now().to_rfc3339(),
+                tweets: tweets.iter().collect::<Vec<&HappyTweet>>(),
+            };
+            return Ok(vec![serde_json::to_value(envelope)?]);
+        }
+        Ok(tweets
+            .chunks(WEBHOOK_BATCH_SIZE)
+            .map(|batch| match self {
+                WebhookFormat::Discord => json!({
+                    "username": "happy-tweet",
+                    "embeds": batch.iter().map(|tweet| json!({
+                        "author": {
+                            "name": format!("@{}", tweet.user.username),
+                            "icon_url": tweet.user.profile_image_url,
+                        },
+                        "title": tweet.tweet.created_at,
+                        "url": tweet.tweet.url,
+                        "description": truncate_chars(&tweet.tweet.content, DISCORD_DESCRIPTION_LIMIT),
+                        "color": score_color(tweet),
+                    })).collect::<Vec<Value>>(),
+                }),
+                _ => json!({
+                    "text": format!("Tweets about {}", term),
+                    "attachments": batch.iter().map(|tweet| json!({
+                        "color": format!("#{:06x}", score_color(tweet)),
+                        "blocks": [
+                            {
+                                "type": "context",
+                                "elements": [
+                                    {
+                                        "type": "image",
+                                        "image_url": tweet.user.profile_image_url,
+                                        "alt_text": tweet.user.username,
+                                    },
+                                    {
+                                        "type": "mrkdwn",
+                                        "text": format!("*<{}|@{}>* · {}", tweet.tweet.url, tweet.user.username, tweet.tweet.created_at),
+                                    },
+                                ],
+                            },
+                            {
+                                "type": "section",
+                                "text": {
+                                    "type": "plain_text",
+                                    "text": truncate_chars(&tweet.tweet.content, SLACK_TEXT_LIMIT),
+                                },
+                            },
+                        ],
+                    })).collect::<Vec<Value>>(),
+                }),
+            })
+            .collect())
+    }
+}
+
+/// RGB color from red to green following the probability of the tweet being
+/// positive, grey when it was not classified
+fn score_color(tweet: &HappyTweet) -> u32 {
+    match &tweet.sentiment {
+        Some(sentiment) => {
+            let happiness = happiness(sentiment);
+            let red = ((1.0 - happiness) * 255.0).round() as u32;
+            let green = (happiness * 255.0).round() as u32;
+            (red << 16) | (green << 8)
+        }
+        None => 0x99aab5,
+    }
+}
+
+/// Cuts `text` to at most `limit` bytes, on a char boundary
+fn truncate_bytes(text: &str, limit: usize) -> &str {
+    if text.len() <= limit {
+        return text;
+    }
+    let end = (0..=limit)
+        .rev()
+        .find(|&i| text.is_char_boundary(i))
+        .unwrap_or_default();
+    &text[..end]
+}
+
+/// Cuts `text` to at most `limit` characters, ending it with an ellipsis
+fn truncate_chars(text: &str, limit: usize) -> String {
+    if text.chars().count() <= limit {
+        return text.to_string();
+    }
+    if limit == 0 {
+        return String::new();
+    }
+    let mut truncated = text.chars().take(limit - 1).collect::<String>();
+    truncated.push('…');
+    truncated
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq)]
+enum ModelDevice {
+    Auto,
+    Cpu,
+    Cuda,
+}
+
+impl ModelDevice {
+    /// Falls back to the CPU when CUDA is requested but not available
+    fn tch_device(self) -> Device {
+        match self {
+            ModelDevice::Auto => Device::cuda_if_available(),
+            ModelDevice::Cpu => Device::Cpu,
+            ModelDevice::Cuda if Cuda::is_available() => Device::Cuda(0),
+            ModelDevice::Cuda => {
+                println!("⚠️  CUDA is not available, the model will run on the CPU");
+                Device::Cpu
+            }
+        }
+    }
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq)]
+enum ModelPreset {
+    English,
+    Multilingual,
+}
+
+impl ModelPreset {
+    fn config(self, device: Device) -> ModelConfig {
+        match self {
+            ModelPreset::English => ModelConfig {
+                config: SentimentConfig {
+                    device,
+                    ..Default::default()
+                },
+                positive_id: DEFAULT_POSITIVE_ID,
+                id: String::from("english"),
+            },
+            ModelPreset::Multilingual => {
+                let resource = |file| Resource::Remote(RemoteResource::from_pretrained(file));
+                ModelConfig {
+                    config: SentimentConfig {
+                        model_type: ModelType::XLMRoberta,
+                        model_resource: resource(MULTILINGUAL_MODEL),
+                        config_resource: resource(MULTILINGUAL_CONFIG),
+                        vocab_resource: resource(MULTILINGUAL_VOCAB),
+                        merges_resource: None,
+                        lower_case: false,
+                        strip_accents: None,
+                        add_prefix_space: None,
+                        device,
+                    },
+                    positive_id: MULTILINGUAL_POSITIVE_ID,
+                    id: String::from("multilingual"),
+                }
+            }
+        }
+    }
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq)]
+enum CustomModelType {
+    Bert,
+    #[clap(name = "distilbert")]
+    DistilBert,
+    Roberta,
+    #[clap(name = "xlm-roberta")]
+    XlmRoberta,
+    Albert,
+    #[clap(name = "mobilebert")]
+    MobileBert,
+    #[clap(name = "xlnet")]
+    XlNet,
+    Bart,
+    Longformer,
+    #[clap(name = "fnet")]
+    FNet,
+}
+
+impl From<CustomModelType> for ModelType {
+    fn from(model_type: CustomModelType) -> ModelType {
+        match model_type {
+            CustomModelType::Bert => ModelType::Bert,
+            CustomModelType::DistilBert => ModelType::DistilBert,
+            CustomModelType::Roberta => ModelType::Roberta,
+            CustomModelType::XlmRoberta => ModelType::XLMRoberta,
+            CustomModelType::Albert => ModelType::Albert,
+            CustomModelType::MobileBert => ModelType::MobileBert,
+            CustomModelType::XlNet => ModelType::XLNet,
+            CustomModelType::Bart => ModelType::Bart,
+            CustomModelType::Longformer => ModelType::Longformer,
+            CustomModelType::FNet => ModelType::FNet,
+        }
+    }
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq)]
+enum SentimentFilter {
+    Positive,
+    Negative,
+    All,
+}
+
+impl SentimentFilter {
+    fn keeps(self, polarity: &SentimentPolarity) -> bool {
+        match self {
+            SentimentFilter::Positive => *polarity == SentimentPolarity::Positive,
+            SentimentFilter::Negative => *polarity == SentimentPolarity::Negative,
+            SentimentFilter::All => true,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct User {
+    username: String,
+    profile_image_url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    location: Option<String>,
+    /// Kind of verified badge, e.g. "blue" or "business"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    verified_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    profile_banner_url: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Tweet {
+    url: String,
+    content: String,
+    created_at: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    hashtags: Vec<String>,
+    /// Topics Twitter annotated the tweet with
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    context: Vec<String>,
+    /// English translation of the content classified with `--translate`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    translation: Option<String>,
+    /// Change of the happiness score made by `--emoji-boost`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    emoji_boost: Option<f64>,
+    /// App the tweet was posted with, from `--include-source-app`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    source: Option<String>,
+    /// Bucket of the score from `--mood`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    mood: Option<String>,
+    /// Tweets the tweet answers, oldest first, from `--with-context`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    thread: Vec<ThreadTweet>,
+    /// Terms of the searches that found the tweet. There are several when the
+    /// outputs of different terms are combined
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    matched_terms: Vec<String>,
+    /// Probability of the tweet being about the `--topic`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    topic_score: Option<f64>,
+    /// Probability of the conversation of a reply being positive, from
+    /// `--conversation-agg`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    conversation_score: Option<f64>,
+}
+
+impl Tweet {
+    /// Adds the `terms` the tweet wasn't matched by yet
+    fn add_matched_terms(&mut self, terms: &[String]) {
+        for term in terms {
+            if !self.matched_terms.contains(term) {
+                self.matched_terms.push(term.clone());
+            }
+        }
+    }
+}
+
+/// Tweet of the conversation a kept reply answers
+#[derive(Debug, Serialize, Deserialize)]
+struct ThreadTweet {
+    username: String,
+    url: String,
+    content: String,
+    created_at: String,
+    /// Probability of the tweet being positive, from `--conversation-agg`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    score: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct HappyTweet {
+    user: User,
+    tweet: Tweet,
+    #[serde(skip_serializing, skip_deserializing)]
+    sentiment: Option<Sentiment>,
+}
+
+/// Self-describing output written with `--envelope`
+#[derive(Debug, Serialize, Deserialize)]
+struct Envelope<T> {
+    schema_version: u32,
+    term: String,
+    generated_at: String,
+    tweets: Vec<T>,
+}
+
+/// Any of the shapes of the output file
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum OutputFile {
+    Tweets(Vec<HappyTweet>),
+    Envelope(Envelope<HappyTweet>),
+}
+
+impl OutputFile {
+    fn into_tweets(self) -> Vec<HappyTweet> {
+        match self {
+            OutputFile::Tweets(tweets) => tweets,
+            OutputFile::Envelope(envelope) => envelope.tweets,
+        }
+    }
+}
+
+impl HappyTweet {
+    /// Id of the tweet, the last segment of its url
+    fn id(&self) -> &str {
+        self.tweet.url.rsplit('/').next().unwrap_or_default()
+    }
+
+    fn is_positive(&self) -> bool {
+        matches!(&self.sentiment, Some(sentiment) if sentiment.polarity == SentimentPolarity::Positive)
+    }
+
+    /// Orders the tweets by the time they were posted. Tweet ids grow over
+    /// time, unlike `created_at` which can have any `--date-format`
+    fn chronological(&self, other: &Self) -> Ordering {
+        let id = |tweet: &Self| tweet.id().parse::<u64>().unwrap_or_default();
+        id(self).cmp(&id(other))
+    }
+}
+
+impl PartialEq for HappyTweet {
+    fn eq(&self, other: &Self) -> bool {
+        self.tweet.url == other.tweet.url
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct TwitterApiResponseData {
+    text: String,
+    created_at: String,
+    author_id: String,
+    id: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    referenced_tweets: Vec<TwitterApiReferencedTweet>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    entities: Option<TwitterApiEntities>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    context_annotations: Vec<TwitterApiContextAnnotation>,
+    /// Language Twitter detected, as a BCP 47 tag
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    lang: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    source: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    possibly_sensitive: Option<bool>,
+}
+
+impl TwitterApiResponseData {
+    /// Id of the tweet this one replies to
+    fn replied_to(&self) -> Option<&str> {
+        self.referenced_tweets
+            .iter()
+            .find(|reference| reference.reference_type == "replied_to")
+            .map(|reference| reference.id.as_str())
+    }
+
+    /// Hashtags of the tweet without `#`, as Twitter parsed them
+    fn hashtags(&self) -> impl Iterator<Item = &str> {
+        self.entities
+            .iter()
+            .flat_map(|entities| entities.hashtags.iter())
+            .map(|hashtag| hashtag.tag.as_str())
+    }
+
+    /// Names of the domains and entities of the context annotations, once
+    /// each. Tweets without annotations have none
+    fn context(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = Vec::new();
+        for annotation in &self.context_annotations {
+            for name in [&annotation.domain.name, &annotation.entity.name] {
+                if !names.contains(&name.as_str()) {
+                    names.push(name);
+                }
+            }
+        }
+        names
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct TwitterApiContextAnnotation {
+    domain: TwitterApiContextName,
+    entity: TwitterApiContextName,
+}
+
+/// Domain or entity of a context annotation, only the name is kept
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct TwitterApiContextName {
+    name: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct TwitterApiEntities {
+    #[serde(default)]
+    hashtags: Vec<TwitterApiHashtag>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct TwitterApiHashtag {
+    tag: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct TwitterApiReferencedTweet {
+    #[serde(rename = "type")]
+    reference_type: String,
+    id: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct TwitterApiResponseUser {
+    id: String,
+    username: String,
+    name: String,
+    profile_image_url: String,
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    location: Option<String>,
+    /// "none" for authors without a badge
+    #[serde(default)]
+    verified_type: Option<String>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct TwitterApiResponseIncludes {
+    users: Vec<TwitterApiResponseUser>,
+    /// Tweets referenced by the ones found, only with `--collapse-retweets`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tweets: Vec<TwitterApiResponseData>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct TwitterApiResponseMeta {
+    #[serde(default)]
+    newest_id: String,
+    #[serde(default)]
+    oldest_id: String,
+    result_count: u16,
+    next_token: Option<String>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct TwitterApiResponse {
+    /// Left out by Twitter when nothing was found
+    #[serde(default)]
+    data: Vec<TwitterApiResponseData>,
+    #[serde(default)]
+    includes: TwitterApiResponseIncludes,
+    meta: TwitterApiResponseMeta,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct TwitterApiResponseError {
+    #[serde(default)]
+    resource_id: String,
+    #[serde(rename = "type")]
+    error_type: String,
+}
+
+/// Tweet of the filtered stream, one per line
+#[derive(Debug, Deserialize)]
+struct TwitterApiStreamMessage {
+    data: TwitterApiResponseData,
+    includes: TwitterApiResponseIncludes,
+}
+
+#[derive(Default, Debug, Deserialize)]
+struct TwitterApiStreamRules {
+    #[serde(default)]
+    data: Vec<TwitterApiStreamRule>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TwitterApiStreamRule {
+    id: String,
+    #[serde(default)]
+    tag: Option<String>,
+}
+
+/// Tweets looked up by id with their authors
+#[derive(Default, Debug, Deserialize)]
+struct TwitterApiTweetsResponse {
+    #[serde(default)]
+    data: Vec<TwitterApiResponseData>,
+    #[serde(default)]
+    includes: TwitterApiResponseIncludes,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct TwitterApiLookupResponse {
+    #[serde(default)]
+    errors: Vec<TwitterApiResponseError>,
+}
+
+/// User of the v1.1 `users/lookup` endpoint, only for its banner
+#[derive(Debug, Deserialize)]
+struct TwitterApiLegacyUser {
+    screen_name: String,
+    #[serde(default)]
+    profile_banner_url: Option<String>,
+}
+
+/// Daily sentiment of a term, stored as a row of the trend CSV
+#[derive(Debug, Serialize, Deserialize)]
+struct TrendRow {
+    date: String,
+    term: String,
+    positive_count: usize,
+    negative_count: usize,
+    mean_score: f64,
+    total: usize,
+}
+
+impl TrendRow {
+    fn positive_ratio(&self) -> f64 {
+        if self.total == 0 {
+            0.0
+        } else {
+            self.positive_count as f64 / self.total as f64
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Arguments::parse();
+    if let Some(threads) = args.classify_threads {
+        tch::set_num_threads(threads as i32);
+    }
+    match &args.command {
+        Some(Command::Prune { file, token }) => {
+            let client = twitter_client(&args.header, &args.user_agent)?;
+            return prune(file, &client, &bearer_token(token.clone()), &args).await;
+        }
+        Some(Command::Trend { file, term }) => return print_trend(file, term.as_deref()),
+        Some(Command::Merge {
+            files,
+            output,
+            format,
+            dedup_fuzzy,
+            overwrite,
+        }) => {
+            let format = format
+                .or_else(|| OutputFormat::from_path(output))
+                .unwrap_or(OutputFormat::Json);
+            let locale = resolve_locale(args.locale.as_deref());
+            return merge(files, output, format, *dedup_fuzzy, *overwrite, locale);
+        }
+        Some(Command::Stream {
+            term,
+            output,
+            format,
+            sentiment,
+            pipe,
+            token,
+        }) => {
+            let format = format
+                .or_else(|| OutputFormat::from_path(output))
+                .unwrap_or(OutputFormat::Ndjson);
+            let bearer = bearer_token(token.clone());
+            let pipe = pipe.as_deref().map(Pipe::spawn).transpose()?;
+            return stream(term, output, format, *sentiment, pipe, &bearer, &args).await;
+        }
+        Some(Command::Peek {
+            file,
+            head,
+            tail,
+            no_color,
+        }) => {
+            return peek(
+                file,
+                *head,
+                *tail,
+                !no_color,
+                resolve_locale(args.locale.as_deref()),
+            )
+        }
+        Some(Command::Operators) => return print_operators(),
+        Some(Command::Try {
+            sentences,
+            no_color,
+        }) => return try_sentences(sentences, &args, !no_color).await,
+        Some(Command::Analyze { file }) => return analyze(file),
+        Some(Command::Validate { file, date_format }) => {
+            return validate_file(file, date_format.as_deref())
+        }
+        Some(Command::Batch { file, summary }) => {
+            return batch(file, summary.as_deref(), &args).await
+        }
+        None => {}
+    }
+    let stats = search(&args, &mut HashMap::new()).await?;
+    if stats.pages > 0 && stats.fetched == 0 {
+        std::process::exit(NO_RESULTS_EXIT_CODE);
+    }
+    Ok(())
+}
+
+/// Job of a JSON jobs file of `batch`
+#[derive(Debug, Deserialize)]
+struct BatchJob {
+    term: String,
+    #[serde(default)]
+    output: Option<std::path::PathBuf>,
+    /// Language of the job, as `--lang`
+    #[serde(default)]
+    lang: Option<String>,
+    /// Any other flag, as on the command line
+    #[serde(default)]
+    args: Vec<String>,
+}
+
+/// Outcome of a job of `batch`, for its summary
+#[derive(Debug, Serialize)]
+struct BatchReport {
+    job: String,
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    fetched: usize,
+    kept: usize,
+    duration_secs: f64,
+}
+
+/// Reads the jobs of a `batch` file as the words of their command lines
+fn read_jobs(path: &std::path::Path) -> Result<Vec<Vec<String>>, String> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| format!("🙅 Can't read the jobs file `{}`: {}", path.display(), e))?;
+    if !contents.trim_start().starts_with('[') {
+        return contents
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(split_words)
+            .collect();
+    }
+    let jobs: Vec<BatchJob> = serde_json::from_str(&contents).map_err(|e| {
+        format!(
+            "🙅 The jobs file `{}` must be a JSON array of jobs with a `term`: {}",
+            path.display(),
+            e
+        )
+    })?;
+    Ok(jobs
+        .into_iter()
+        .map(|job| {
+            let mut words = vec![job.term];
+            if let Some(lang) = job.lang {
+                words.extend([String::from("--lang"), lang]);
+            }
+            if let Some(output) = job.output {
+                words.extend([String::from("-o"), output.display().to_string()]);
+            }
+            words.extend(job.args);
+            words
+        })
+        .collect())
+}
+
+/// Splits a line of a jobs file into words like a shell does, keeping the
+/// spaces between quotes
+fn split_words(line: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote = None;
+    for c in line.chars() {
+        match (quote, c) {
+            (Some(open), c) if c == open => quote = None,
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
+            }
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (_, c) => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quote.is_some() {
+        return Err(format!("🙅 The job `{}` has an unclosed quote", line));
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// Runs the jobs of a `batch` file in order, sharing the classifiers between
+/// them. A failed job doesn't stop the next ones, the summary tells which
+/// failed and the batch fails at the end. The `--header`, `--user-agent` and
+/// `--token` of the batch apply to every job
+async fn batch(
+    path: &std::path::Path,
+    summary: Option<&std::path::Path>,
+    args: &Arguments,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let jobs = read_jobs(path)?;
+    let mut shared = Vec::new();
+    for header in &args.header {
+        shared.extend([String::from("--header"), header.clone()]);
+    }
+    if args.user_agent != USER_AGENT {
+        shared.extend([String::from("--user-agent"), args.user_agent.clone()]);
+    }
+    if let Some(token) = &args.token {
+        shared.extend([String::from("--token"), token.clone()]);
+    }
+    let mut pool = HashMap::new();
+    let mut reports = Vec::new();
+    for (i, words) in jobs.iter().enumerate() {
+        let job = words.join(" ");
+        println!("\n🗂️  Job {} of {}: {}", i + 1, jobs.len(), job);
+        let started = Instant::now();
+        let argv = std::iter::once(String::from("happy-tweet"))
+            .chain(shared.iter().cloned())
+            .chain(words.iter().cloned());
+        let result = match Arguments::try_parse_from(argv) {
+            Ok(job_args) if job_args.command.is_some() => {
+                Err(String::from("a job can't run a subcommand"))
+            }
+            Ok(job_args) => search(&job_args, &mut pool)
+                .await
+                .map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string().lines().next().unwrap_or_default().to_string()),
+        };
+        let duration_secs = started.elapsed().as_secs_f64();
+        reports.push(match result {
+            Ok(stats) => BatchReport {
+                job,
+                status: if stats.pages > 0 && stats.fetched == 0 {
+                    "empty"
+                } else {
+                    "ok"
+                },
+                error: None,
+                fetched: stats.fetched,
+                kept: stats.kept,
+                duration_secs,
+            },
+            Err(error) => {
+                println!("⚠️  Job {} failed, going on with the next one", i + 1);
+                BatchReport {
+                    job,
+                    status: "failed",
+                    error: Some(error),
+                    fetched: 0,
+                    kept: 0,
+                    duration_secs,
+                }
+            }
+        });
+    }
+
+    let mut table = Table::new();
+    table
+        .load_preset(UTF8_FULL)
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(vec!["Job", "Status", "Fetched", "Kept", "Seconds"]);
+    for report in &reports {
+        table.add_row(vec![
+            report.job.clone(),
+            report.status.to_string(),
+            report.fetched.to_string(),
+            report.kept.to_string(),
+            format!("{:.1}", report.duration_secs),
+        ]);
+    }
+    println!("\n{}", table);
+    for (i, report) in reports.iter().enumerate() {
+        if let Some(error) = &report.error {
+            println!("  - Job {}: {}", i + 1, error);
+        }
+    }
+    if let Some(summary) = summary {
+        let mut writer = BufWriter::new(File::create(summary)?);
+        serde_json::to_writer_pretty(&mut writer, &reports)?;
+        writer.flush()?;
+    }
+    let failed = reports
+        .iter()
+        .filter(|report| report.error.is_some())
+        .count();
+    if failed > 0 {
+        Err(format!("🙅 {} of {} jobs failed", failed, reports.len()))?;
+    }
+    println!("✅ All {} jobs finished", reports.len());
+    Ok(())
+}
+
+/// Searches the term of `args` and writes the tweets found, returning the
+/// stats of the run. The classifier is taken from `pool` if it has one of the
+/// same models, and left in it, so the jobs of a batch load each model once
+async fn search(
+    args: &Arguments,
+    pool: &mut HashMap<String, LazyClassifier>,
+) -> Result<RunStats, Box<dyn std::error::Error>> {
+    let started_at = Utc::now();
+    let started = Instant::now();
+    let term = args
+        .term
+        .clone()
+        .expect("the term is required without a subcommand");
+    let query = QueryBuilder::from_args(&term, args).build()?;
+    if args.print_query {
+        println!("{}", query);
+        return Ok(RunStats::default());
+    }
+    if args.format.len() > args.output.len() {
+        Err(String::from(
+            "🙅 There are more `--format` than `--output`. Each format applies to the output in the same position.",
+        ))?;
+    }
+    let filters = tweet_filters(args)?;
+    let authors = AuthorList::from_args(args)?;
+    let profiles = ProfileFilter::from_args(args);
+    if args.window && args.window_stride > args.max_content_bytes {
+        Err(String::from(
+            "🙅 `--window-stride` can't be greater than `--max-content-bytes`, the text between the windows wouldn't be classified",
+        ))?;
+    }
+    if let (Some(min), Some(max)) = (args.expect_min, args.expect_max) {
+        if min > max {
+            Err(String::from(
+                "🙅 `--expect-min` can't be greater than `--expect-max`",
+            ))?;
+        }
+    }
+    let timezone = args.timezone.unwrap_or(Tz::UTC);
+    let mut window = TimeWindow {
+        since: args
+            .since
+            .as_deref()
+            .map(|date| parse_local_date(date, timezone))
+            .transpose()?,
+        until: args
+            .until
+            .as_deref()
+            .map(|date| parse_local_date(date, timezone))
+            .transpose()?,
+        since_id: None,
+    };
+    // Timelines aren't limited to the last days like the recent search
+    if args.timeline.is_none() {
+        validate_time_window(&window, Utc::now())?;
+    }
+    let balance = args.balance.as_deref().map(parse_balance).transpose()?;
+    // Printed even when picked at random, so any selection can be reproduced
+    let seed = (args.sample.is_some() || balance.is_some())
+        .then(|| args.seed.unwrap_or_else(rand::random));
+    let outputs = resolve_outputs(args);
+    let state_path = match (&args.state_file, args.since_last_run) {
+        (Some(path), _) => Some(path.clone()),
+        (None, true) => match outputs.first() {
+            Some((path, _)) if path != std::path::Path::new(STDOUT_PATH) => {
+                Some(path.with_extension("state.json"))
+            }
+            _ => Err(String::from(
+                "🙅 `--since-last-run` needs an output file or a `--state-file` to keep the state in",
+            ))?,
+        },
+        (None, false) => None,
+    };
+    let last_run = match &state_path {
+        Some(path) if args.since_last_run => Some(RunState::load(path, &term)?),
+        _ => None,
+    };
+    if let Some(last_run) = &last_run {
+        last_run.narrow(&mut window, Utc::now());
+    }
+    for (path, format) in &outputs {
+        if *format == OutputFormat::Ndjson && args.envelope {
+            Err(format!(
+                "🙅 `{}` is NDJSON, which can't be wrapped in an envelope",
+                path.display()
+            ))?;
+        }
+        if *format == OutputFormat::Table
+            && (args.split_output || path != std::path::Path::new(STDOUT_PATH))
+        {
+            Err(format!(
+                "🙅 The table format is only meant for the terminal, use JSON, NDJSON or Markdown to write `{}`",
+                path.display()
+            ))?;
+        }
+    }
+    let (seen, resume_token) = if args.resume {
+        let path = outputs
+            .iter()
+            .find(|(_, format)| *format == OutputFormat::Ndjson)
+            .map(|(path, _)| path)
+            .ok_or_else(|| String::from("🙅 `--resume` needs an NDJSON output"))?;
+        let (seen, token) = read_resume(path)?;
+        if token.is_some() {
+            println!(
+                "⏩ Resuming the search of `{}` after its {} tweets",
+                path.display(),
+                seen.len()
+            );
+        }
+        (seen, token)
+    } else {
+        (HashSet::new(), None)
+    };
+    // Outputs that aren't regular files, like the default `/dev/stdout`,
+    // have no fingerprints
+    let dedup_window = args.dedup_window.as_deref().map(parse_window).transpose()?;
+    let mut fingerprints = outputs
+        .iter()
+        .map(|(path, _)| {
+            (args.compact_dedup && (path.is_file() || !path.exists()))
+                .then(|| Fingerprints::load(path, dedup_window))
+                .transpose()
+        })
+        .collect::<Result<Vec<Option<Fingerprints>>, String>>()?;
+    if balance.is_some() && args.sentiment != SentimentFilter::All {
+        Err(String::from(
+            "🙅 `--balance` needs both sentiments, add `--sentiment all`",
+        ))?;
+    }
+    if let Some(cache_dir) = &args.cache_dir {
+        // rust-bert reads it the first time a resource is resolved
+        env::set_var(MODEL_CACHE_ENV_NAME, cache_dir);
+    }
+    // `--ensemble` runs the default model and the custom one
+    let (model, ensemble) = match (args.no_sentiment, args.ensemble) {
+        (true, _) => (None, None),
+        (false, false) => (Some(model_config(args)?), None),
+        (false, true) => (
+            Some(args.model.config(args.device.tch_device())),
+            Some(model_config(args)?),
+        ),
+    };
+    println!("Starting...");
+    let bearer = bearer_token(args.token.clone());
+
+    let usage_path = args.usage_file.clone().unwrap_or_else(default_usage_path);
+    let mut usage = ApiUsage::load(&usage_path)?;
+    let mut fetched = 0;
+    let normalizer = ContentNormalizer::new(args);
+
+    // Identifies the models of the classifier, for the sentiment cache and the
+    // pool shared by the jobs of `batch`
+    let classifier_id = model.as_ref().map(|model| match &ensemble {
+        Some(second) => format!("{}+{}:{}", model.id, second.id, args.ensemble_threshold),
+        None => model.id.clone(),
+    });
+    let mut classifier = match (model, &classifier_id) {
+        (Some(model), Some(id)) => {
+            let cache = args
+                .sentiment_cache
+                .as_deref()
+                .map(|path| SentimentCache::load(path, id))
+                .transpose()?;
+            let batch_timeout = args.classify_batch_timeout.map(Duration::from_secs);
+            let windows = args.window.then_some(Windows {
+                stride: args.window_stride,
+                aggregate: args.window_aggregate,
+            });
+            Some(match pool.remove(id) {
+                Some(mut classifier) => {
+                    classifier.cache = cache;
+                    classifier.max_content_bytes = args.max_content_bytes;
+                    classifier.windows = windows;
+                    classifier.normalizer = normalizer;
+                    classifier.strip_emoji = args.strip_emoji;
+                    if let Some(ensemble) = &mut classifier.ensemble {
+                        ensemble.compared = 0;
+                        ensemble.agreed = 0;
+                    }
+                    classifier
+                }
+                None => LazyClassifier {
+                    model: Some(model),
+                    buffer_size: args.channel_buffer,
+                    batch_timeout,
+                    max_content_bytes: args.max_content_bytes,
+                    windows,
+                    normalizer,
+                    strip_emoji: args.strip_emoji,
+                    classifier: None,
+                    cache,
+                    ensemble: ensemble.map(|second| {
+                        Box::new(Ensemble {
+                            classifier: LazyClassifier {
+                                model: Some(second),
+                                buffer_size: args.channel_buffer,
+                                batch_timeout,
+                                max_content_bytes: args.max_content_bytes,
+                                windows: None,
+                                normalizer: None,
+                                strip_emoji: false,
+                                classifier: None,
+                                cache: None,
+                                ensemble: None,
+                            },
+                            threshold: args.ensemble_threshold,
+                            compared: 0,
+                            agreed: 0,
+                        })
+                    }),
+                },
+            })
+        }
+        _ => None,
+    };
+    let mut classified: HashMap<String, Sentiment> = HashMap::new();
+    // Tweets the model failed on, with the reason, dropped before writing
+    let mut unclassifiable: HashMap<String, String> = HashMap::new();
+    let mut translator = args
+        .translate
+        .then(|| Translator::new(args.device.tch_device()));
+    let mut topic_classifier = args
+        .topic
+        .clone()
+        .map(|topic| TopicClassifier::new(topic, args.device.tch_device()));
+    let mut translations: HashMap<String, String> = HashMap::new();
+    let mood_thresholds = parse_mood_thresholds(&args.mood_thresholds)?;
+    let emoji_table = args
+        .emoji_boost
+        .map(|_| emoji_polarities(args.emoji_table.as_deref()))
+        .transpose()?;
+    // Tweets passing the filters so far, only counted with `--adaptive-paging`
+    let mut kept = 0;
+    let adaptive_total = args.max_total.filter(|_| args.adaptive_paging);
+
+    let fields = SearchFields::new(args);
+    let client = twitter_client(&args.header, &args.user_agent)?;
+    let limiter = RateLimiter::from_args(args)?;
+    let mut dump = args
+        .dump_raw
+        .as_deref()
+        .map(|path| RawDump::create(path, &bearer))
+        .transpose()?;
+    let timeline_user = match args.timeline {
+        Some(Timeline::Mentions) => Some(user_id(&client, &limiter, &bearer, &term).await?),
+        None => None,
+    };
+    let mut data = TwitterApiResponse::default();
+    data.meta.next_token = resume_token;
+    // Newest tweet found, which the first page has
+    let mut newest_id = None;
+    let mut pages = 0;
+    let mut failures: Vec<String> = Vec::new();
+    let spinner = Spinner::new(!args.quiet && std::io::stderr().is_terminal());
+    for _ in 0..args.max_pages {
+        let remaining = args.quota.map(|quota| quota.saturating_sub(usage.tweets));
+        // Twitter sends pages of at least `MIN_RESULTS` tweets, and counts
+        // all of them
+        if remaining.is_some_and(|remaining| remaining < MIN_RESULTS) {
+            println!(
+                "⚠️  The quota of {} tweets for this month has been reached, {} tweets were pulled. Stopping the search.",
+                args.quota.unwrap_or_default(),
+                usage.tweets
+            );
+            break;
+        }
+        let wanted = match adaptive_total {
+            Some(max_total) => adaptive_page_size(max_total, kept, fetched),
+            None => MAX_RESULTS,
+        };
+        let max_results = remaining
+            .unwrap_or(MAX_RESULTS)
+            .min(wanted)
+            .clamp(MIN_RESULTS, MAX_RESULTS);
+        let next_token = data.meta.next_token.as_deref();
+        let url = match &timeline_user {
+            Some(id) => mentions_url(id, &fields, &window, max_results, next_token)?,
+            None => search_url(&query, &fields, &window, max_results, next_token)?,
+        };
+        spinner.set(format!("Fetching page {}…", pages + 1));
+        let res = get_respecting_rate_limit(&client, &limiter, url, &bearer).await;
+        spinner.clear();
+        if let Ok(res) = &res {
+            if matches!(
+                res.status(),
+                StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN
+            ) {
+                Err(format!(
+                    "🙅 Twitter rejected the bearer token ({}). Check it: https://github.com/onmax/happy-tweet#twitter-bearer-token",
+                    res.status()
+                ))?;
+            }
+        }
+        let mut page = match read_page(res, dump.as_mut()).await {
+            Ok(page) => page,
+            Err(error) if args.keep_going => {
+                println!(
+                    "⚠️  Page {} failed ({}), keeping the {} tweets found so far",
+                    pages + 1,
+                    error,
+                    data.data.len()
+                );
+                failures.push(format!("page {}: {}", pages + 1, error));
+                break;
+            }
+            Err(error) => Err(format!(
+                "🙅 No response when requesting tweets ({}). Check your term.",
+                error
+            ))?,
+        };
+        pages += 1;
+        if newest_id.is_none() && !page.meta.newest_id.is_empty() {
+            newest_id = Some(page.meta.newest_id.clone());
+        }
+
+        if let Some(normalizer) = normalizer.filter(|_| !args.raw_content) {
+            for tweet in &mut page.data {
+                tweet.text = normalizer.normalize(&tweet.text);
+            }
+        }
+
+        fetched += page.data.len();
+        usage.tweets += page.data.len();
+        usage.save(&usage_path)?;
+
+        if adaptive_total.is_some() {
+            // The authors are only dropped after collapsing, so they're checked
+            // here as the ones the tweets will be written with
+            let kept_authors = (authors.is_some() || profiles.is_some()).then(|| {
+                page.includes
+                    .users
+                    .iter()
+                    .filter(|user| {
+                        authors
+                            .as_ref()
+                            .is_none_or(|authors| authors.keeps(&user.username))
+                            && profiles
+                                .as_ref()
+                                .is_none_or(|profiles| profiles.keeps(user))
+                    })
+                    .map(|user| user.id.as_str())
+                    .collect::<HashSet<&str>>()
+            });
+            let candidates = page
+                .data
+                .iter()
+                .filter(|tweet| {
+                    !seen.contains(&tweet.id)
+                        && filters.iter().all(|filter| filter.keep(tweet))
+                        && kept_authors.as_ref().is_none_or(|kept| {
+                            kept.contains(written_author(
+                                tweet,
+                                &page.includes,
+                                args.collapse_retweets,
+                            ))
+                        })
+                })
+                .collect::<Vec<&TwitterApiResponseData>>();
+            match &mut classifier {
+                Some(_) if candidates.is_empty() => {}
+                Some(classifier) => {
+                    if let Some(translator) = &mut translator {
+                        translator
+                            .translate_tweets(&candidates, &mut translations)
+                            .await?;
+                    }
+                    let texts = candidates
+                        .iter()
+                        .map(|tweet| {
+                            translations
+                                .get(&tweet.id)
+                                .unwrap_or(&tweet.text)
+                                .to_owned()
+                        })
+                        .collect();
+                    spinner.set(classifier.phase());
+                    let sentiments = classifier.predict(texts).await?;
+                    spinner.clear();
+                    for (tweet, sentiment) in candidates.iter().zip(sentiments) {
+                        match sentiment {
+                            Ok(sentiment) => {
+                                if args.sentiment.keeps(&sentiment.polarity) {
+                                    kept += 1;
+                                }
+                                classified.insert(tweet.id.to_owned(), sentiment);
+                            }
+                            Err(reason) => {
+                                unclassifiable.insert(tweet.id.to_owned(), reason);
+                            }
+                        }
+                    }
+                }
+                None => kept += candidates.len(),
+            }
+        }
+
+        data.data.extend(page.data);
+        data.includes.users.extend(page.includes.users);
+        data.includes.tweets.extend(page.includes.tweets);
+        data.meta = page.meta;
+        if data.meta.next_token.is_none() || adaptive_total.is_some_and(|total| kept >= total) {
+            break;
+        }
+    }
+
+    if pages > 0 && fetched == 0 && failures.is_empty() {
+        // Nothing to classify or write, so the model is never loaded
+        println!("🤷 Twitter found no tweets for `{}`", term);
+        let mut stats = RunStats {
+            term: term.clone(),
+            started_at: started_at.to_rfc3339(),
+            pages,
+            api_calls: pages + usize::from(timeline_user.is_some()),
+            ..RunStats::default()
+        };
+        if let Some(stats_path) = &args.stats_json {
+            stats.write(stats_path, started)?;
+        }
+        check_expected(0, args.expect_min, args.expect_max)?;
+        if let Some(path) = &state_path {
+            RunState::new(&term, started_at, newest_id, last_run).save(path)?;
+        }
+        if let (Some(id), Some(classifier)) = (classifier_id, classifier) {
+            pool.insert(id, classifier);
+        }
+        return Ok(stats);
+    }
+
+    // Without a warning, tweets missing before the oldest found would look
+    // like there were no older ones
+    let oldest = data
+        .data
+        .iter()
+        .filter_map(|tweet| posted_at(&tweet.id))
+        .min();
+    if let (Some(since), Some(oldest)) = (window.since, oldest) {
+        let date = |date: DateTime<Utc>| date.to_rfc3339_opts(SecondsFormat::Secs, true);
+        if data.meta.next_token.is_some() && oldest > since {
+            println!(
+                "⚠️  The tweets found only go back to {}, the run stopped after {} pages before reaching `--since` {}. Raise `--max-pages` or continue with `--resume` to get the older ones",
+                date(oldest),
+                pages,
+                date(since)
+            );
+        } else if timeline_user.is_some()
+            && oldest > since
+            && fetched + MAX_RESULTS > MENTIONS_TIMELINE_LIMIT
+        {
+            println!(
+                "⚠️  The mentions only go back to {}, not to `--since` {}: the timeline only keeps the last {} mentions",
+                date(oldest),
+                date(since),
+                MENTIONS_TIMELINE_LIMIT
+            );
+        }
+    }
+
+    // TODO remove duplicates
+
+    if !seen.is_empty() {
+        let total = data.data.len();
+        data.data.retain(|tweet| !seen.contains(&tweet.id));
+        println!(
+            "⏭️  Skipped {} tweets already in the output",
+            total - data.data.len()
+        );
+    }
+
+    if args.collapse_retweets {
+        let collapsed = collapse_retweets(&mut data);
+        println!("🔁 Collapsed {} retweets onto their originals", collapsed);
+    }
+
+    // After collapsing, so a retweet is checked with the author and profile of
+    // the original that replaces it
+    if let Some(authors) = &authors {
+        let authors_dropped = retain_authors(&mut data, |user| authors.keeps(&user.username));
+        println!(
+            "👤 Dropped {} tweets of authors {}",
+            authors_dropped,
+            if authors.only {
+                "out of `--only-authors`"
+            } else {
+                "in `--exclude-authors`"
+            }
+        );
+    }
+    if let Some(profiles) = &profiles {
+        let profiles_dropped = retain_authors(&mut data, |user| profiles.keeps(user));
+        println!(
+            "🥚 Dropped {} tweets of authors {}",
+            profiles_dropped,
+            profiles.missing()
+        );
+    }
+
+    if !filters.is_empty() {
+        let dropped;
+        (data.data, dropped) = apply_filters(&filters, std::mem::take(&mut data.data));
+        for (filter, dropped) in filters.iter().zip(&dropped) {
+            println!("{}", filter.report(dropped));
+        }
+    }
+
+    if !fingerprints.is_empty() {
+        let total = data.data.len();
+        let mut seen = HashSet::new();
+        data.data.retain(|tweet| {
+            let fingerprint = fingerprint(&tweet.text);
+            !fingerprints
+                .iter()
+                .flatten()
+                .any(|fingerprints| fingerprints.contains(&fingerprint))
+                && seen.insert(fingerprint)
+        });
+        println!(
+            "🧹 Dropped {} tweets with the same content as one already written",
+            total - data.data.len()
+        );
+    }
+
+    if let Some(threshold) = args.dedup_fuzzy {
+        let total = data.data.len();
+        data.data = dedup_fuzzy(
+            data.data,
+            threshold,
+            |tweet| &tweet.text,
+            |a, b| a.created_at.cmp(&b.created_at),
+        );
+        println!(
+            "🧹 Dropped {} near-duplicate tweets",
+            total - data.data.len()
+        );
+    }
+
+    let sentiments: Vec<Option<Sentiment>> = if let Some(classifier) = &mut classifier {
+        // Tweets classified while paging are not classified again
+        let pending = data
+            .data
+            .iter()
+            .filter(|tweet| {
+                !classified.contains_key(&tweet.id) && !unclassifiable.contains_key(&tweet.id)
+            })
+            .collect::<Vec<&TwitterApiResponseData>>();
+        if let Some(translator) = &mut translator {
+            translator
+                .translate_tweets(&pending, &mut translations)
+                .await?;
+        }
+        let (ids, tweets_string): (Vec<String>, Vec<String>) = pending
+            .iter()
+            .map(|tweet| {
+                let text = translations.get(&tweet.id).unwrap_or(&tweet.text);
+                (tweet.id.to_owned(), text.to_owned())
+            })
+            .unzip();
+        if !tweets_string.is_empty() {
+            spinner.set(classifier.phase());
+            let sentiments = classifier.predict(tweets_string).await?;
+            spinner.clear();
+            for (id, sentiment) in ids.into_iter().zip(sentiments) {
+                match sentiment {
+                    Ok(sentiment) => {
+                        classified.insert(id, sentiment);
+                    }
+                    Err(reason) => {
+                        unclassifiable.insert(id, reason);
+                    }
+                }
+            }
+        }
+        if let Some(cache) = &classifier.cache {
+            cache.save()?;
+        }
+        if let Some(agreement) = classifier.ensemble.as_ref().and_then(|e| e.agreement()) {
+            println!(
+                "🤝 Both models agreed on the sentiment of {:.1}% of the tweets",
+                agreement * 100.0
+            );
+        }
+        data.data
+            .retain(|tweet| match unclassifiable.get(&tweet.id) {
+                Some(reason) => {
+                    println!(
+                        "⚠️  Dropped tweet {}, the model failed to classify it: {}",
+                        tweet.id, reason
+                    );
+                    false
+                }
+                None => true,
+            });
+        data.data
+            .iter()
+            .map(|tweet| classified.remove(&tweet.id))
+            .collect()
+    } else {
+        data.data.iter().map(|_| None).collect()
+    };
+
+    // convert data to a vector of Tweets
+    let mut tweets: Vec<HappyTweet> = Vec::new();
+    let mut missing_authors = 0;
+    for (tweet, mut sentiment) in data.data.iter().zip(sentiments) {
+        let emoji_boost = match (&mut sentiment, args.emoji_boost, &emoji_table) {
+            (Some(sentiment), Some(delta), Some(table)) => {
+                boost_emoji(sentiment, &tweet.text, delta, table)
+            }
+            _ => None,
+        };
+        // The originals of collapsed retweets come without their author when
+        // it is suspended, protected or withheld
+        let Some(user) = data.includes.users.iter().find(|u| u.id == tweet.author_id) else {
+            missing_authors += 1;
+            continue;
+        };
+        let tweet = HappyTweet {
+            tweet: Tweet {
+                content: tweet.text.clone(),
+                url: format!("https://twitter.com/{}/status/{}", user.username, tweet.id),
+                hashtags: tweet.hashtags().map(String::from).collect(),
+                context: tweet.context().into_iter().map(String::from).collect(),
+                translation: translations.remove(&tweet.id),
+                emoji_boost,
+                source: tweet.source.clone().filter(|s| !s.is_empty()),
+                mood: sentiment
+                    .as_ref()
+                    .filter(|_| args.mood)
+                    .map(|sentiment| mood(happiness(sentiment), mood_thresholds).to_string()),
+                thread: Vec::new(),
+                matched_terms: vec![term.clone()],
+                topic_score: None,
+                conversation_score: None,
+                created_at: format_created_at(
+                    &tweet.created_at,
+                    args.date_format.as_deref(),
+                    args.timezone,
+                )?,
+            },
+            user: User {
+                username: user.username.to_string(),
+                profile_image_url: user.profile_image_url.to_string(),
+                // The bio is also fetched by `--require-bio`, which doesn't
+                // write it
+                description: user
+                    .description
+                    .clone()
+                    .filter(|d| args.author_details && !d.is_empty()),
+                location: user
+                    .location
+                    .clone()
+                    .filter(|l| args.author_details && !l.is_empty()),
+                verified_type: user
+                    .verified_type
+                    .clone()
+                    .filter(|v| !v.is_empty() && v != "none"),
+                profile_banner_url: None,
+            },
+            sentiment,
+        };
+        tweets.push(tweet);
+    }
+    if missing_authors > 0 {
+        println!(
+            "⚠️  Dropped {} tweets whose author Twitter left out, like suspended or protected accounts",
+            missing_authors
+        );
+    }
+
+    if let Some(trend_path) = &args.trend {
+        update_trend(trend_path, &term, &tweets)?;
+    }
+
+    let mut stats = RunStats {
+        term: term.clone(),
+        started_at: started_at.to_rfc3339(),
+        duration_secs: 0.0,
+        pages,
+        api_calls: pages + failures.len() + usize::from(timeline_user.is_some()),
+        fetched,
+        failures: failures.clone(),
+        unclassifiable: unclassifiable.len(),
+        seed,
+        ensemble_agreement: classifier
+            .as_ref()
+            .and_then(|classifier| classifier.ensemble.as_ref())
+            .and_then(|ensemble| ensemble.agreement()),
+        ..Default::default()
+    };
+    for sentiment in tweets.iter().filter_map(|tweet| tweet.sentiment.as_ref()) {
+        stats.classified += 1;
+        match sentiment.polarity {
+            SentimentPolarity::Positive => stats.positive += 1,
+            SentimentPolarity::Negative => stats.negative += 1,
+        }
+        let bucket = (happiness(sentiment) * HISTOGRAM_BUCKETS as f64) as usize;
+        stats.score_histogram[bucket.min(HISTOGRAM_BUCKETS - 1)] += 1;
+    }
+
+    // Filter tweets to only keep the ones with the requested sentiment, all
+    // of them if they were not classified
+    let mut tweets = tweets
+        .into_iter()
+        .filter(|tweet| {
+            if let Some(sentiment) = &tweet.sentiment {
+                args.sentiment.keeps(&sentiment.polarity)
+            } else {
+                args.no_sentiment
+            }
+        })
+        .collect::<Vec<HappyTweet>>();
+
+    if let Some(topic_classifier) = topic_classifier.as_mut().filter(|_| !tweets.is_empty()) {
+        spinner.set("Scoring the topic…");
+        let texts = tweets
+            .iter()
+            .map(|tweet| {
+                tweet
+                    .tweet
+                    .translation
+                    .clone()
+                    .unwrap_or_else(|| tweet.tweet.content.clone())
+            })
+            .collect();
+        let scores = topic_classifier.scores(texts).await?;
+        spinner.clear();
+        let total = tweets.len();
+        tweets = tweets
+            .into_iter()
+            .zip(scores)
+            .filter(|(_, score)| *score >= args.topic_threshold)
+            .map(|(mut tweet, score)| {
+                tweet.tweet.topic_score = Some(score);
+                tweet
+            })
+            .collect();
+        println!(
+            "🧭 Dropped {} tweets not about \"{}\"",
+            total - tweets.len(),
+            topic_classifier.topic
+        );
+    }
+
+    if let Some(seed) = seed {
+        println!(
+            "🎲 Selecting at random with the seed {}, add `--seed {}` to get the same tweets again",
+            seed, seed
+        );
+    }
+
+    if let (Some(ratio), Some(seed)) = (balance, seed) {
+        let count = |tweets: &[HappyTweet]| {
+            let positives = tweets.iter().filter(|tweet| tweet.is_positive()).count();
+            (positives, tweets.len() - positives)
+        };
+        let (positives, negatives) = count(&tweets);
+        tweets = balance_tweets(tweets, ratio, seed);
+        let (balanced_positives, balanced_negatives) = count(&tweets);
+        println!(
+            "⚖️  Balanced {} positive and {} negative tweets to {} and {}",
+            positives, negatives, balanced_positives, balanced_negatives
+        );
+    }
+
+    if let Some(max_total) = args.max_total {
+        tweets.truncate(max_total);
+    }
+
+    if let (Some(sample), Some(seed)) = (args.sample, seed) {
+        tweets = sample_tweets(tweets, sample, seed);
+    }
+
+    match args.sort {
+        Some(order) => {
+            let half_life = parse_window(&args.half_life)?;
+            order.sort(&mut tweets, Utc::now(), half_life);
+        }
+        // Twitter sends the pages newest first, sorting them anyway keeps
+        // the order the same when it doesn't
+        None => tweets.sort_by(|a, b| b.chronological(a)),
+    }
+
+    stats.kept = tweets.len();
+
+    if args.with_context {
+        let replies = data
+            .data
+            .iter()
+            .filter_map(|tweet| Some((tweet.id.as_str(), tweet.replied_to()?)))
+            .collect::<HashMap<&str, &str>>();
+        let requests =
+            fetch_threads(&client, &limiter, &bearer, &mut tweets, &replies, args).await?;
+        stats.api_calls += requests;
+    }
+
+    if let (Some(aggregate), Some(classifier)) = (args.conversation_agg, &mut classifier) {
+        spinner.set(classifier.phase());
+        score_conversations(&mut tweets, classifier, aggregate).await?;
+        spinner.clear();
+    }
+
+    if args.author_badges {
+        stats.api_calls += fetch_banners(
+            &client,
+            &limiter,
+            &bearer,
+            &mut tweets,
+            args.fetch_concurrency,
+        )
+        .await?;
+    }
+
+    if let Some(webhook) = &args.webhook {
+        // The `--header` flags are meant for Twitter, not for the webhook
+        let webhook_client = reqwest::Client::builder()
+            .user_agent(&args.user_agent)
+            .build()?;
+        let payloads = args.webhook_format.payloads(&term, &tweets)?;
+        for (i, payload) in payloads.iter().enumerate() {
+            if i > 0 {
+                // Chat webhooks are rate limited per channel
+                time::sleep(WEBHOOK_BATCH_DELAY).await;
+            }
+            post_webhook(
+                &webhook_client,
+                webhook,
+                payload,
+                args.webhook_secret.as_deref(),
+                &limiter.retry_budget,
+            )
+            .await?;
+        }
+        println!(
+            "📨 Posted {} tweets to {} in {} messages",
+            tweets.len(),
+            webhook,
+            payloads.len()
+        );
+    }
+
+    let options = WriteOptions {
+        overwrite: args.overwrite,
+        envelope: args.envelope.then(|| term.clone()),
+        flush_every: args.flush_every,
+        recover: args.recover,
+        color: !args.no_color,
+        locale: resolve_locale(args.locale.as_deref()),
+        fields: args.fields.clone(),
+        encoding: args.output_encoding,
+        indent: parse_indent(&args.indent)?,
+        next_token: data.meta.next_token.clone(),
+    };
+    let (positive, negative): (Vec<&HappyTweet>, Vec<&HappyTweet>) =
+        tweets.iter().partition(|tweet| tweet.is_positive());
+    let raw = args.raw_passthrough.then(|| raw_response(&data, &tweets));
+    let mut written = Vec::new();
+    spinner.set("Writing…");
+    for &(ref output_path, format) in &outputs {
+        if args.pipe.is_some() && output_path == std::path::Path::new(STDOUT_PATH) {
+            continue;
+        }
+        if let Some(raw) = &raw {
+            let total = write_raw(
+                raw,
+                output_path,
+                args.overwrite,
+                args.recover,
+                args.output_encoding,
+                &options.indent,
+            )
+            .map_err(|error| {
+                rescue_output(
+                    output_path,
+                    error,
+                    || serde_json::to_vec(raw),
+                    "json",
+                    args.stdout_on_write_error,
+                )
+            })?;
+            written.push((output_path.clone(), total));
+        } else if args.split_output {
+            for (bucket, sentiment) in [(&positive, "positive"), (&negative, "negative")] {
+                let path = split_output_path(output_path, sentiment);
+                let total = write_tweets(bucket, &path, format, &options).map_err(|error| {
+                    rescue_output(
+                        &path,
+                        error,
+                        || ndjson_lines(bucket),
+                        "ndjson",
+                        args.stdout_on_write_error,
+                    )
+                })?;
+                written.push((path, total));
+            }
+        } else {
+            let all = tweets.iter().collect::<Vec<&HappyTweet>>();
+            let total = write_tweets(&all, output_path, format, &options).map_err(|error| {
+                rescue_output(
+                    output_path,
+                    error,
+                    || ndjson_lines(&tweets),
+                    "ndjson",
+                    args.stdout_on_write_error,
+                )
+            })?;
+            written.push((output_path.clone(), total));
+        }
+    }
+    // Saved once every output is written, so a failed output never leaves
+    // fingerprints of tweets that aren't in it
+    for fingerprints in fingerprints.iter_mut().flatten() {
+        for tweet in &tweets {
+            fingerprints.insert(&tweet.tweet.url, &tweet.tweet.content);
+        }
+        fingerprints.save()?;
+    }
+    if let Some(command) = &args.pipe {
+        spinner.set("Piping…");
+        let mut pipe = Pipe::spawn(command)?;
+        match &raw {
+            Some(raw) => pipe.send(raw)?,
+            None => {
+                for tweet in &tweets {
+                    pipe.send(tweet)?;
+                }
+            }
+        }
+        pipe.finish()?;
+    }
+    spinner.clear();
+    let locale = options.locale;
+    if let [(path, total)] = written.as_slice() {
+        println!(
+            "\n\n✅  Finish! Retrieved {} tweets. Check {}",
+            locale.number(*total),
+            path.display()
+        );
+    } else if written.is_empty() {
+        println!(
+            "\n\n✅  Finish! Retrieved {} tweets",
+            locale.number(tweets.len())
+        );
+    } else {
+        println!("\n\n✅  Finish! Check:");
+        for (path, total) in &written {
+            println!("  - {} ({} tweets)", path.display(), locale.number(*total));
+        }
+    }
+    match args.quota {
+        Some(quota) => println!(
+            "📊 Pulled {} tweets from the Twitter API, {} of {} this month",
+            locale.number(fetched),
+            locale.number(usage.tweets),
+            locale.number(quota)
+        ),
+        None => println!(
+            "📊 Pulled {} tweets from the Twitter API, {} this month",
+            locale.number(fetched),
+            locale.number(usage.tweets)
+        ),
+    }
+    report_failures(&failures);
+    if let Some(stats_path) = &args.stats_json {
+        stats.write(stats_path, started)?;
+    }
+    check_expected(tweets.len(), args.expect_min, args.expect_max)?;
+    if let Some(path) = &state_path {
+        RunState::new(&term, started_at, newest_id, last_run).save(path)?;
+    }
+    if let (Some(id), Some(classifier)) = (classifier_id, classifier) {
+        pool.insert(id, classifier);
+    }
+
+    Ok(stats)
+}
+
+/// Summary of a run for dashboards, written with `--stats-json`
+#[derive(Default, Debug, Serialize)]
+struct RunStats {
+    term: String,
+    started_at: String,
+    duration_secs: f64,
+    pages: usize,
+    /// Requests made to the Twitter API
+    api_calls: usize,
+    fetched: usize,
+    classified: usize,
+    positive: usize,
+    negative: usize,
+    /// Tweets written once every filter is applied
+    kept: usize,
+    /// Classified tweets by probability of being positive, from 0 to 1
+    score_histogram: [usize; HISTOGRAM_BUCKETS],
+    /// Pages that failed with `--keep-going`
+    failures: Vec<String>,
+    /// Tweets dropped because the model failed to classify them
+    unclassifiable: usize,
+    /// Seed of `--sample` and `--balance`, given with `--seed` or picked at random
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+    /// Share of the tweets both models of `--ensemble` gave the same polarity
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ensemble_agreement: Option<f64>,
+}
+
+/// Spinner telling the phase a search is in, e.g. while the model loads
+/// without printing anything. It is drawn on the standard error from its own
+/// thread, and only shows between `set` and `clear` so it never gets mixed
+/// with the messages of the search
+struct Spinner {
+    /// Phase shown, if any
+    label: Arc<Mutex<Option<String>>>,
+    enabled: bool,
+}
+
+impl Spinner {
+    fn new(enabled: bool) -> Spinner {
+        let label = Arc::new(Mutex::new(None::<String>));
+        if enabled {
+            let shown = Arc::downgrade(&label);
+            thread::spawn(move || {
+                for frame in SPINNER_FRAMES.iter().cycle() {
+                    thread::sleep(SPINNER_INTERVAL);
+                    // The spinner is gone with its search
+                    let Some(shown) = shown.upgrade() else {
+                        break;
+                    };
+                    let label = shown.lock().unwrap();
+                    if let Some(label) = &*label {
+                        eprint!("\r\x1b[2K{} {}", frame, label);
+                    }
+                }
+            });
+        }
+        Spinner { label, enabled }
+    }
+
+    fn set(&self, label: impl Into<String>) {
+        if self.enabled {
+            *self.label.lock().unwrap() = Some(label.into());
+        }
+    }
+
+    /// Hides the spinner, erasing its line
+    fn clear(&self) {
+        if self.label.lock().unwrap().take().is_some() {
+            eprint!("\r\x1b[2K");
+        }
+    }
+}
+
+impl Drop for Spinner {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+/// Lists what failed with `--keep-going` once the results are written
+fn report_failures(failures: &[String]) {
+    if failures.is_empty() {
+        return;
+    }
+    println!("⚠️  The results are partial, {} failed:", failures.len());
+    for failure in failures {
+        println!("  - {}", failure);
+    }
+}
+
+/// Reads a page of search results, or why it couldn't be read. The body is
+/// written to the `--dump-raw` file before being parsed, even when it fails
+async fn read_page(
+    res: Result<reqwest::Response, String>,
+    dump: Option<&mut RawDump>,
+) -> Result<TwitterApiResponse, String> {
+    let res = res?;
+    let status = res.status();
+    let url = res.url().to_string();
+    let body = res.text().await.map_err(|e| e.to_string())?;
+    if let Some(dump) = dump {
+        dump.write(&url, status, &body).map_err(|e| {
+            format!(
+                "🙅 Can't write the raw page to `{}`: {}",
+                dump.path.display(),
+                e
+            )
+        })?;
+    }
+    if !status.is_success() {
+        return Err(format!("Twitter answered {}", status));
+    }
+    serde_json::from_str(&body).map_err(|e| e.to_string())
+}
+
+/// NDJSON file of `--dump-raw`, with a line for every page of search results
+/// as it was received
+struct RawDump {
+    path: std::path::PathBuf,
+    writer: BufWriter<File>,
+    /// Bearer token removed from the pages in case Twitter ever echoes it
+    bearer: String,
+}
+
+impl RawDump {
+    fn create(path: &std::path::Path, bearer: &str) -> std::io::Result<RawDump> {
+        Ok(RawDump {
+            path: path.to_path_buf(),
+            writer: BufWriter::new(File::create(path)?),
+            bearer: bearer.to_string(),
+        })
+    }
+
+    /// Writes a page, as JSON when it is or else as the text received, and
+    /// flushes it so the pages before a crash are kept
+    fn write(&mut self, url: &str, status: StatusCode, body: &str) -> std::io::Result<()> {
+        let body = if self.bearer.is_empty() {
+            body.to_string()
+        } else {
+            body.replace(&self.bearer, "[REDACTED]")
+        };
+        let body = serde_json::from_str::<Value>(&body).unwrap_or(Value::String(body));
+        let line = json!({
+            "url": url,
+            "status": status.as_u16(),
+            "received_at": Utc::now().to_rfc3339(),
+            "body": body,
+        });
+        serde_json::to_writer(&mut self.writer, &line)?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()
+    }
+}
+
+impl RunStats {
+    fn write(
+        &mut self,
+        path: &std::path::Path,
+        started: Instant,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.duration_secs = started.elapsed().as_secs_f64();
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Fails when the number of kept tweets is out of the `--expect-min` and
+/// `--expect-max` range, once the outputs are written
+fn check_expected(kept: usize, min: Option<usize>, max: Option<usize>) -> Result<(), String> {
+    match (min, max) {
+        (Some(min), _) if kept < min => Err(format!(
+            "🙅 Expected at least {} tweets (`--expect-min`) but {} were kept",
+            min, kept
+        )),
+        (_, Some(max)) if kept > max => Err(format!(
+            "🙅 Expected at most {} tweets (`--expect-max`) but {} were kept",
+            max, kept
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Probability of a tweet being positive whatever the predicted polarity, so
+/// scores of both polarities can be averaged
+fn happiness(sentiment: &Sentiment) -> f64 {
+    match sentiment.polarity {
+        SentimentPolarity::Positive => sentiment.score,
+        SentimentPolarity::Negative => 1.0 - sentiment.score,
+    }
+}
+
+/// Reads the emoji of `--emoji-table`, or the built-in ones
+fn emoji_polarities(path: Option<&std::path::Path>) -> Result<HashMap<String, f64>, String> {
+    let path = match path {
+        Some(path) => path,
+        None => {
+            return Ok(EMOJI_POLARITIES
+                .iter()
+                .map(|&(emoji, polarity)| (emoji.to_string(), polarity))
+                .collect())
+        }
+    };
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| format!("🙅 Can't read the emoji table `{}`: {}", path.display(), e))?;
+    serde_json::from_str(&contents).map_err(|e| {
+        format!(
+            "🙅 The emoji table `{}` must be a JSON object of emoji and numbers: {}",
+            path.display(),
+            e
+        )
+    })
+}
+
+/// Whether `c` is an emoji or a part of an emoji sequence, like a skin tone,
+/// a keycap or the variation selector that shows the emoji of a symbol
+fn is_emoji(c: char) -> bool {
+    matches!(
+        c as u32,
+        0x1F000..=0x1FAFF | 0x2600..=0x27BF | 0x2B00..=0x2BFF | 0xFE0F | 0x20E3 | 0xE0020..=0xE007F
+    )
+}
+
+/// `text` without its emoji, for `--strip-emoji`. Zero-width joiners are only
+/// removed after an emoji, since some scripts need them. A text of only emoji
+/// is kept as it is, as the model would get nothing to classify
+fn strip_emoji(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut after_emoji = false;
+    for c in text.chars() {
+        if is_emoji(c) || (c == '\u{200D}' && after_emoji) {
+            after_emoji = true;
+        } else {
+            after_emoji = false;
+            stripped.push(c);
+        }
+    }
+    if stripped.trim().is_empty() {
+        text.to_string()
+    } else {
+        stripped
+    }
+}
+
+/// Moves the happiness of the sentiment by `delta` towards the side of the
+/// emoji in `text`, which can flip its polarity. Returns the change made, if
+/// any
+fn boost_emoji(
+    sentiment: &mut Sentiment,
+    text: &str,
+    delta: f64,
+    table: &HashMap<String, f64>,
+) -> Option<f64> {
+    let net: f64 = table
+        .iter()
+        .map(|(emoji, polarity)| text.matches(emoji.as_str()).count() as f64 * polarity)
+        .sum();
+    if net == 0.0 {
+        return None;
+    }
+    let before = happiness(sentiment);
+    let after = (before + delta * net.signum()).clamp(0.0, 1.0);
+    if after == before {
+        return None;
+    }
+    *sentiment = if after >= 0.5 {
+        Sentiment {
+            polarity: SentimentPolarity::Positive,
+            score: after,
+        }
+    } else {
+        Sentiment {
+            polarity: SentimentPolarity::Negative,
+            score: 1.0 - after,
+        }
+    };
+    Some(after - before)
+}
+
+/// Records the sentiment of the classified tweets in the trend file. Each
+/// term gets one row per day, so running again the same day replaces it
+fn update_trend(
+    path: &std::path::Path,
+    term: &str,
+    tweets: &[HappyTweet],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let sentiments = tweets
+        .iter()
+        .filter_map(|tweet| tweet.sentiment.as_ref())
+        .collect::<Vec<&Sentiment>>();
+    let positive_count = sentiments
+        .iter()
+        .filter(|sentiment| sentiment.polarity == SentimentPolarity::Positive)
+        .count();
+    let mean_score = if sentiments.is_empty() {
+        0.0
+    } else {
+        sentiments.iter().map(|s| happiness(s)).sum::<f64>() / sentiments.len() as f64
+    };
+    let row = TrendRow {
+        date: Utc::now().format("%Y-%m-%d").to_string(),
+        term: term.to_string(),
+        positive_count,
+        negative_count: sentiments.len() - positive_count,
+        mean_score,
+        total: sentiments.len(),
+    };
+
+    let mut rows = if path.exists() {
+        read_trend(path)?
+    } else {
+        Vec::new()
+    };
+    rows.retain(|r| !(r.date == row.date && r.term == row.term));
+    rows.push(row);
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for row in rows {
+        writer.serialize(row)?;
+    }
+    write_atomically(path, &writer.into_inner()?)?;
+    Ok(())
+}
+
+fn read_trend(path: &std::path::Path) -> Result<Vec<TrendRow>, String> {
+    csv::Reader::from_path(path)
+        .and_then(|mut reader| reader.deserialize().collect())
+        .map_err(|e| format!("🙅 Can't read the trend file `{}`: {}", path.display(), e))
+}
+
+/// Prints the trend rows as a table with a sparkline of the positive ratio
+fn print_trend(
+    path: &std::path::Path,
+    term: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let rows = read_trend(path)?
+        .into_iter()
+        .filter(|row| term.is_none_or(|term| row.term == term))
+        .collect::<Vec<TrendRow>>();
+    if rows.is_empty() {
+        println!("Nothing recorded yet in {}", path.display());
+        return Ok(());
+    }
+
+    let spark = |ratio: f64| SPARKS[(ratio * (SPARKS.len() - 1) as f64).round() as usize];
+    println!(
+        "{:<10}  {:>8}  {:>8}  {:>5}  {:>5}  term",
+        "date", "positive", "negative", "ratio", "score"
+    );
+    for row in &rows {
+        println!(
+            "{:<10}  {:>8}  {:>8}  {:>5.2}  {:>5.2}  {}",
+            row.date,
+            row.positive_count,
+            row.negative_count,
+            row.positive_ratio(),
+            row.mean_score,
+            row.term
+        );
+    }
+    println!(
+        "\nPositive ratio: {}",
+        rows.iter()
+            .map(|row| spark(row.positive_ratio()))
+            .collect::<String>()
+    );
+    Ok(())
+}
+
+/// Fetches the tweets each reply of `tweets` answers, following `replies`
+/// up to `THREAD_DEPTH` tweets, with at most `THREAD_MAX_REQUESTS` lookups of
+/// 100 tweets. Returns the number of requests made
+async fn fetch_threads(
+    client: &reqwest::Client,
+    limiter: &RateLimiter,
+    bearer: &str,
+    tweets: &mut [HappyTweet],
+    replies: &HashMap<&str, &str>,
+    args: &Arguments,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    // Tweet of the output and the next tweet of its thread to fetch
+    let mut pending = tweets
+        .iter()
+        .enumerate()
+        .filter_map(|(i, tweet)| Some((i, replies.get(tweet.id())?.to_string())))
+        .collect::<Vec<(usize, String)>>();
+    let mut requests = 0;
+    let mut capped = false;
+    for _ in 0..THREAD_DEPTH {
+        let ids = pending
+            .iter()
+            .map(|(_, id)| id.as_str())
+            .collect::<HashSet<&str>>()
+            .into_iter()
+            .collect::<Vec<&str>>();
+        let mut found: HashMap<String, (TwitterApiResponseData, String)> = HashMap::new();
+        let mut urls = Vec::new();
+        for batch in ids.chunks(MAX_RESULTS) {
+            if requests + urls.len() == THREAD_MAX_REQUESTS {
+                println!(
+                    "⚠️  Stopped fetching the threads after {} requests",
+                    THREAD_MAX_REQUESTS
+                );
+                capped = true;
+                break;
+            }
+            urls.push(Url::parse_with_params(
+                &api_url("/2/tweets"),
+                &[
+                    ("ids", batch.join(",")),
+                    ("tweet.fields", String::from("created_at,referenced_tweets")),
+                    ("expansions", String::from("author_id")),
+                ],
+            )?);
+        }
+        requests += urls.len();
+        for res in get_all(client, limiter, bearer, urls, args.fetch_concurrency).await? {
+            if !res.status().is_success() {
+                Err(format!(
+                    "🙅 Couldn't fetch the threads, Twitter answered {}",
+                    res.status()
+                ))?;
+            }
+            let lookup = res.json::<TwitterApiTweetsResponse>().await?;
+            for tweet in lookup.data {
+                // Parents of deleted or protected authors are missing
+                if let Some(user) = lookup
+                    .includes
+                    .users
+                    .iter()
+                    .find(|u| u.id == tweet.author_id)
+                {
+                    found.insert(tweet.id.clone(), (tweet, user.username.clone()));
+                }
+            }
+        }
+
+        let mut next = Vec::new();
+        for (i, id) in pending {
+            if let Some((parent, username)) = found.get(&id) {
+                tweets[i].tweet.thread.insert(
+                    0,
+                    ThreadTweet {
+                        username: username.clone(),
+                        url: format!("https://twitter.com/{}/status/{}", username, parent.id),
+                        content: parent.text.clone(),
+                        created_at: format_created_at(
+                            &parent.created_at,
+                            args.date_format.as_deref(),
+                            args.timezone,
+                        )?,
+                        score: None,
+                    },
+                );
+                if let Some(grandparent) = parent.replied_to() {
+                    next.push((i, grandparent.to_string()));
+                }
+            }
+        }
+        pending = next;
+        if pending.is_empty() || capped {
+            break;
+        }
+    }
+    Ok(requests)
+}
+
+/// Classifies the threads of the replies of `tweets` and combines their scores
+/// with the score of the reply into its `conversation_score`. Thread tweets the
+/// model fails on are left out of it
+async fn score_conversations(
+    tweets: &mut [HappyTweet],
+    classifier: &mut LazyClassifier,
+    aggregate: ConversationAggregate,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let contents = tweets
+        .iter()
+        .filter(|tweet| tweet.sentiment.is_some())
+        .flat_map(|tweet| tweet.tweet.thread.iter())
+        .map(|parent| parent.content.clone())
+        .collect::<Vec<String>>();
+    if contents.is_empty() {
+        return Ok(());
+    }
+    let mut sentiments = classifier.predict(contents).await?.into_iter();
+    for tweet in tweets.iter_mut() {
+        let reply = match &tweet.sentiment {
+            Some(sentiment) if !tweet.tweet.thread.is_empty() => happiness(sentiment),
+            _ => continue,
+        };
+        for parent in &mut tweet.tweet.thread {
+            parent.score = sentiments
+                .next()
+                .and_then(Result::ok)
+                .map(|sentiment| happiness(&sentiment));
+        }
+        let scores = tweet
+            .tweet
+            .thread
+            .iter()
+            .filter_map(|parent| parent.score)
+            .chain([reply])
+            .collect::<Vec<f64>>();
+        tweet.tweet.conversation_score = Some(aggregate.aggregate(&scores));
+    }
+    Ok(())
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq)]
+enum ConversationAggregate {
+    /// Mean score of the tweets of the conversation
+    Mean,
+    /// Mean weighted by position, the oldest tweet counting once and each
+    /// newer one once more, so the reply weighs the most
+    Weighted,
+    /// Score of the reply, the last word of the conversation
+    Last,
+}
+
+impl ConversationAggregate {
+    /// Combines the `scores` of a conversation, oldest first
+    fn aggregate(self, scores: &[f64]) -> f64 {
+        match self {
+            ConversationAggregate::Mean => scores.iter().sum::<f64>() / scores.len() as f64,
+            ConversationAggregate::Weighted => {
+                let weights = (1..=scores.len()).map(|weight| weight as f64);
+                let total = weights.clone().sum::<f64>();
+                scores
+                    .iter()
+                    .zip(weights)
+                    .map(|(score, weight)| score * weight)
+                    .sum::<f64>()
+                    / total
+            }
+            ConversationAggregate::Last => scores.last().copied().unwrap_or(0.5),
+        }
+    }
+}
+
+/// Adds the profile banner of the authors of `tweets`, looking them up by
+/// 100 on the v1.1 API. Without elevated access Twitter denies it, and the
+/// banners are left out with a warning. Returns the number of requests made
+async fn fetch_banners(
+    client: &reqwest::Client,
+    limiter: &RateLimiter,
+    bearer: &str,
+    tweets: &mut [HappyTweet],
+    concurrency: usize,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let usernames = tweets
+        .iter()
+        .map(|tweet| tweet.user.username.to_lowercase())
+        .collect::<HashSet<String>>()
+        .into_iter()
+        .collect::<Vec<String>>();
+    let mut banners: HashMap<String, String> = HashMap::new();
+    let urls = usernames
+        .chunks(MAX_RESULTS)
+        .map(|batch| {
+            Url::parse_with_params(
+                &api_url("/1.1/users/lookup.json"),
+                &[("screen_name", batch.join(","))],
+            )
+        })
+        .collect::<Result<Vec<Url>, _>>()?;
+    let requests = urls.len();
+    for res in get_all(client, limiter, bearer, urls, concurrency).await? {
+        if matches!(
+            res.status(),
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN
+        ) {
+            println!(
+                "⚠️  Twitter denied the profile banners ({}), they need elevated access to the v1.1 API",
+                res.status()
+            );
+            return Ok(requests);
+        }
+        // Twitter answers 404 when none of the users exists anymore
+        if res.status() == StatusCode::NOT_FOUND {
+            continue;
+        }
+        if !res.status().is_success() {
+            Err(format!(
+                "🙅 Couldn't fetch the profile banners, Twitter answered {}",
+                res.status()
+            ))?;
+        }
+        for user in res.json::<Vec<TwitterApiLegacyUser>>().await? {
+            if let Some(banner) = user.profile_banner_url.filter(|b| !b.is_empty()) {
+                banners.insert(user.screen_name.to_lowercase(), banner);
+            }
+        }
+    }
+    for tweet in tweets.iter_mut() {
+        tweet.user.profile_banner_url = banners.get(&tweet.user.username.to_lowercase()).cloned();
+    }
+    Ok(requests)
+}
+
+/// Replaces the filtered stream rules added by a previous stream with one
+/// matching `term`
+async fn set_stream_rule(
+    client: &reqwest::Client,
+    term: &str,
+    bearer: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let res = client
+        .get(api_url(STREAM_RULES_PATH))
+        .header(AUTHORIZATION, bearer)
+        .send()
+        .await?;
+    if matches!(
+        res.status(),
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN
+    ) {
+        Err(format!(
+            "🙅 Twitter rejected the bearer token ({}). The filtered stream needs a project with elevated access: https://github.com/onmax/happy-tweet#twitter-bearer-token",
+            res.status()
+        ))?;
+    }
+    let rules = res.json::<TwitterApiStreamRules>().await?;
+    let ids = rules
+        .data
+        .into_iter()
+        .filter(|rule| rule.tag.as_deref() == Some(STREAM_RULE_TAG))
+        .map(|rule| rule.id)
+        .collect::<Vec<String>>();
+    let mut changes = vec![json!({ "add": [{ "value": term, "tag": STREAM_RULE_TAG }] })];
+    if !ids.is_empty() {
+        changes.insert(0, json!({ "delete": { "ids": ids } }));
+    }
+    for change in changes {
+        let res = client
+            .post(api_url(STREAM_RULES_PATH))
+            .header(AUTHORIZATION, bearer)
+            .json(&change)
+            .send()
+            .await?;
+        if !res.status().is_success() {
+            Err(format!(
+                "🙅 Couldn't set the stream rule, Twitter answered {}",
+                res.status()
+            ))?;
+        }
+    }
+    Ok(())
+}
+
+/// Classifies the tweets matching `term` as Twitter streams them and appends
+/// the ones with the `sentiment` to the NDJSON output, with the model and
+/// text options of `args` like the search. A dropped stream reconnects
+/// after a `backoff`, which stops growing after `STREAM_MAX_ATTEMPT`. With a
+/// `pipe`, the stream stops once its command exits
+async fn stream(
+    term: &str,
+    output: &std::path::Path,
+    format: OutputFormat,
+    sentiment: SentimentFilter,
+    mut pipe: Option<Pipe>,
+    bearer: &str,
+    args: &Arguments,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Other formats and compressed files would be rewritten whole for every
+    // tweet, or only be complete once the stream ends
+    if format != OutputFormat::Ndjson || Compression::from_path(output).is_some() {
+        Err(format!(
+            "🙅 `stream` appends every tweet to `{}` as it comes, which only plain NDJSON allows. Use `-f ndjson` or an `.ndjson` output",
+            output.display()
+        ))?;
+    }
+    let mut classifier = LazyClassifier::new(args)?;
+    let mut output = (pipe.is_none() || output != std::path::Path::new(STDOUT_PATH))
+        .then(|| StreamOutput::open(output, args.output_encoding))
+        .transpose()?;
+    let client = &twitter_client(&args.header, &args.user_agent)?;
+    set_stream_rule(client, term, bearer).await?;
+    let url = Url::parse_with_params(
+        &api_url(STREAM_PATH),
+        &[
+            ("tweet.fields", "created_at,entities,context_annotations"),
+            ("user.fields", "profile_image_url"),
+            ("expansions", "author_id"),
+        ],
+    )?;
+
+    let mut attempt = 0;
+    loop {
+        let reason = match client.get(url.clone()).header(AUTHORIZATION, bearer).send().await {
+            Ok(res) if res.status().is_success() => {
+                attempt = 0;
+                println!("📡 Following `{}` as it is posted...", term);
+                read_stream(
+                    res,
+                    &mut classifier,
+                    term,
+                    output.as_mut(),
+                    sentiment,
+                    pipe.as_mut(),
+                )
+                .await?
+            }
+            Ok(res)
+                if matches!(
+                    res.status(),
+                    StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN
+                ) =>
+            {
+                Err(format!(
+                    "🙅 Twitter rejected the bearer token ({}). Check it: https://github.com/onmax/happy-tweet#twitter-bearer-token",
+                    res.status()
+                ))?
+            }
+            Ok(res) => format!("Twitter answered {}", res.status()),
+            Err(error) => error.to_string(),
+        };
+        if let Some(closed) = pipe.take_if(|pipe| !pipe.is_open()) {
+            println!(
+                "🚰 `{}` stopped reading, the stream is done",
+                closed.command
+            );
+            closed.finish()?;
+            return Ok(());
+        }
+        attempt = (attempt + 1).min(STREAM_MAX_ATTEMPT);
+        let wait = backoff(attempt);
+        println!(
+            "⚠️  The stream dropped ({}), reconnecting in {:.1} seconds",
+            reason,
+            wait.as_secs_f64()
+        );
+        time::sleep(wait).await;
+    }
+}
+
+/// Reads the tweets of a connected stream until it drops, returning why
+async fn read_stream(
+    mut res: reqwest::Response,
+    classifier: &mut LazyClassifier,
+    term: &str,
+    mut output: Option<&mut StreamOutput>,
+    sentiment: SentimentFilter,
+    mut pipe: Option<&mut Pipe>,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let mut buffer: Vec<u8> = Vec::new();
+    loop {
+        let chunk = match res.chunk().await {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => return Ok(String::from("Twitter closed it")),
+            Err(error) => return Ok(error.to_string()),
+        };
+        buffer.extend_from_slice(&chunk);
+        while let Some(end) = buffer.iter().position(|&byte| byte == b'\n') {
+            let line = buffer.drain(..=end).collect::<Vec<u8>>();
+            // Twitter sends empty lines to keep the connection alive
+            if line.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+            let message = match serde_json::from_slice::<TwitterApiStreamMessage>(&line) {
+                Ok(message) => message,
+                Err(_) => {
+                    println!("⚠️  Skipped a message of the stream that isn't a tweet");
+                    continue;
+                }
+            };
+            let tweet = message.data;
+            let polarity = classifier.predict(vec![tweet.text.clone()]).await?.pop();
+            let polarity = match polarity {
+                Some(Ok(polarity)) if sentiment.keeps(&polarity.polarity) => polarity,
+                Some(Err(reason)) => {
+                    println!(
+                        "⚠️  Skipped tweet {}, the model failed to classify it: {}",
+                        tweet.id, reason
+                    );
+                    continue;
+                }
+                _ => continue,
+            };
+            let user = match message
+                .includes
+                .users
+                .iter()
+                .find(|u| u.id == tweet.author_id)
+            {
+                Some(user) => user,
+                None => continue,
+            };
+            let happy_tweet = HappyTweet {
+                tweet: Tweet {
+                    content: tweet.text.clone(),
+                    url: format!("https://twitter.com/{}/status/{}", user.username, tweet.id),
+                    hashtags: tweet.hashtags().map(String::from).collect(),
+                    context: tweet.context().into_iter().map(String::from).collect(),
+                    translation: None,
+                    emoji_boost: None,
+                    source: None,
+                    mood: None,
+                    thread: Vec::new(),
+                    matched_terms: vec![term.to_string()],
+                    topic_score: None,
+                    conversation_score: None,
+                    created_at: tweet.created_at.clone(),
+                },
+                user: User {
+                    username: user.username.to_string(),
+                    profile_image_url: user.profile_image_url.to_string(),
+                    description: None,
+                    location: None,
+                    verified_type: None,
+                    profile_banner_url: None,
+                },
+                sentiment: Some(polarity),
+            };
+            if let Some(output) = output.as_deref_mut() {
+                output.append(&happy_tweet)?;
+            }
+            if let Some(pipe) = pipe.as_deref_mut() {
+                pipe.send(&happy_tweet)?;
+                if !pipe.is_open() {
+                    return Ok(String::from("the command stopped reading"));
+                }
+            }
+        }
+    }
+}
+
+/// NDJSON output of `stream`, kept open to append every tweet as soon as it is
+/// classified. The tweets already in it are skipped, since a reconnected
+/// stream can send a tweet again
+struct StreamOutput {
+    writer: OutputWriter,
+    urls: HashSet<String>,
+}
+
+impl StreamOutput {
+    fn open(
+        path: &std::path::Path,
+        encoding: OutputEncoding,
+    ) -> Result<StreamOutput, Box<dyn std::error::Error>> {
+        // Special files like `/dev/stdout` have no tweets to read
+        let urls = if path.is_file() {
+            read_ndjson_file(path)?
+                .into_iter()
+                .map(|tweet| tweet.tweet.url)
+                .collect()
+        } else {
+            HashSet::new()
+        };
+        Ok(StreamOutput {
+            writer: OutputWriter::create(path, true, encoding)?,
+            urls,
+        })
+    }
+
+    /// Writes the tweet on its own line and flushes it, so the output can be
+    /// followed while the stream runs
+    fn append(&mut self, tweet: &HappyTweet) -> Result<(), Box<dyn std::error::Error>> {
+        if self.urls.insert(tweet.tweet.url.clone()) {
+            serde_json::to_writer(&mut self.writer, tweet)?;
+            self.writer.write_all(b"\n")?;
+            self.writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Prints a histogram of the scores of an output, with how many tweets reach
+/// each bucket, and the share of positive tweets
+fn analyze(path: &std::path::Path) -> Result<(), Box<dyn std::error::Error>> {
+    let scores = read_scores(path)?;
+    let mut histogram = [0; HISTOGRAM_BUCKETS];
+    for (score, _) in &scores {
+        let bucket = (score * HISTOGRAM_BUCKETS as f64) as usize;
+        histogram[bucket.min(HISTOGRAM_BUCKETS - 1)] += 1;
+    }
+    let max = histogram.iter().copied().max().unwrap_or(0).max(1);
+    println!("Scores of {} tweets in {}\n", scores.len(), path.display());
+    println!(
+        "{:<9}  {:<40}  {:>6}  {:>8}",
+        "score", "", "tweets", "at least"
+    );
+    let mut at_least = scores.len();
+    for (i, count) in histogram.iter().enumerate() {
+        println!(
+            "{:.1}-{:.1}  {:<40}  {:>6}  {:>8}",
+            i as f64 / HISTOGRAM_BUCKETS as f64,
+            (i + 1) as f64 / HISTOGRAM_BUCKETS as f64,
+            "█".repeat(count * 40 / max),
+            count,
+            at_least
+        );
+        at_least -= count;
+    }
+    let positive = scores.iter().filter(|(_, positive)| *positive).count();
+    let ratio = |count: usize| count as f64 * 100.0 / scores.len() as f64;
+    println!(
+        "\nPositive: {} ({:.1}%)  Negative: {} ({:.1}%)",
+        positive,
+        ratio(positive),
+        scores.len() - positive,
+        ratio(scores.len() - positive)
+    );
+    Ok(())
+}
+
+/// Checks the tweets of an output, full or written with `--fields`, against
+/// the structs they are written from, plus what serde can't tell: tweet urls
+/// well-formed, dates parseable, scores from 0 to 1 and no tweet twice. Fails
+/// once every malformed tweet is listed
+fn validate_file(
+    path: &std::path::Path,
+    date_format: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !path.is_file() {
+        Err(format!("🙅 `{}` doesn't exist", path.display()))?;
+    }
+    let contents = read_output(path)?;
+    let entries: Vec<Result<Value, String>> = match serde_json::from_str::<Value>(&contents) {
+        Ok(Value::Array(entries)) => entries.into_iter().map(Ok).collect(),
+        Ok(Value::Object(mut envelope)) => {
+            match envelope.get("schema_version").and_then(Value::as_u64) {
+                Some(version) if version <= SCHEMA_VERSION as u64 => {}
+                Some(version) => Err(format!(
+                    "🙅 `{}` has the schema version {}, newer than the {} of this version of happy-tweet",
+                    path.display(),
+                    version,
+                    SCHEMA_VERSION
+                ))?,
+                None => Err(format!(
+                    "🙅 `{}` is neither a JSON array of tweets nor an envelope",
+                    path.display()
+                ))?,
+            }
+            match envelope.remove("tweets") {
+                Some(Value::Array(entries)) => entries.into_iter().map(Ok).collect(),
+                _ => Err(format!(
+                    "🙅 The envelope of `{}` has no `tweets` array",
+                    path.display()
+                ))?,
+            }
+        }
+        Ok(_) => Err(format!(
+            "🙅 `{}` is neither a JSON array of tweets nor an envelope",
+            path.display()
+        ))?,
+        Err(e) if contents.trim_start().starts_with('[') => {
+            Err(format!("🙅 `{}` isn't valid JSON: {}", path.display(), e))?
+        }
+        Err(_) => contents
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .filter(|line| serde_json::from_str::<Cursor>(line).is_err())
+            .map(|line| serde_json::from_str(line).map_err(|e| format!("not JSON: {}", e)))
+            .collect(),
+    };
+
+    let mut urls: HashMap<String, usize> = HashMap::new();
+    let mut malformed = 0;
+    for (i, entry) in entries.iter().enumerate() {
+        let mut problems = match entry {
+            Ok(entry) => entry_problems(entry, date_format),
+            Err(reason) => vec![reason.clone()],
+        };
+        let url = entry.as_ref().ok().and_then(|entry| {
+            entry
+                .pointer("/tweet/url")
+                .or_else(|| entry.get("url"))?
+                .as_str()
+        });
+        if let Some(url) = url {
+            match urls.get(url) {
+                Some(first) => problems.push(format!("same url as tweet {}", first + 1)),
+                None => {
+                    urls.insert(url.to_string(), i);
+                }
+            }
+        }
+        if !problems.is_empty() {
+            malformed += 1;
+            println!("  - tweet {}: {}", i + 1, problems.join(", "));
+        }
+    }
+    if malformed > 0 {
+        Err(format!(
+            "🙅 {} of the {} tweets of `{}` are malformed",
+            malformed,
+            entries.len(),
+            path.display()
+        ))?;
+    }
+    println!(
+        "✅ The {} tweets of {} are valid",
+        entries.len(),
+        path.display()
+    );
+    Ok(())
+}
+
+/// What is wrong with a tweet of an output. Full tweets have a `tweet` and a
+/// `user`, the ones written with `--fields` only some of the fields
+fn entry_problems(entry: &Value, date_format: Option<&str>) -> Vec<String> {
+    let mut problems = Vec::new();
+    let object = match entry {
+        Value::Object(object) => object,
+        _ => return vec![String::from("not a JSON object")],
+    };
+    if object.contains_key("tweet") || object.contains_key("user") {
+        let tweet = match serde_json::from_value::<HappyTweet>(entry.clone()) {
+            Ok(tweet) => tweet,
+            Err(e) => return vec![e.to_string()],
+        };
+        problems.extend(url_problem("tweet.url", &tweet.tweet.url, true));
+        problems.extend(url_problem(
+            "user.profile_image_url",
+            &tweet.user.profile_image_url,
+            false,
+        ));
+        if let Some(banner) = &tweet.user.profile_banner_url {
+            problems.extend(url_problem("user.profile_banner_url", banner, false));
+        }
+        let mut dates = vec![("tweet.created_at", &tweet.tweet.created_at)];
+        for thread_tweet in &tweet.tweet.thread {
+            problems.extend(url_problem("tweet.thread.url", &thread_tweet.url, true));
+            dates.push(("tweet.thread.created_at", &thread_tweet.created_at));
+        }
+        for (name, date) in dates {
+            if !is_date(date, date_format) {
+                problems.push(format!("`{}` isn't a date: {}", name, date));
+            }
+        }
+        return problems;
+    }
+
+    let known = Field::value_variants()
+        .iter()
+        .map(|field| field.key())
+        .collect::<Vec<&str>>();
+    for (key, value) in object {
+        if !known.contains(&key.as_str()) {
+            problems.push(format!("unknown field `{}`", key));
+            continue;
+        }
+        match (key.as_str(), value) {
+            (_, Value::Null) => {}
+            ("url", Value::String(url)) => problems.extend(url_problem(key, url, true)),
+            ("profile_image_url" | "profile_banner_url", Value::String(url)) => {
+                problems.extend(url_problem(key, url, false))
+            }
+            ("created_at", Value::String(date)) if !is_date(date, date_format) => {
+                problems.push(format!("`{}` isn't a date: {}", key, date))
+            }
+            ("score", value) if !value.as_f64().is_some_and(|s| (0.0..=1.0).contains(&s)) => {
+                problems.push(format!("`score` isn't a number from 0 to 1: {}", value))
+            }
+            ("sentiment", value) if !matches!(value.as_str(), Some("positive" | "negative")) => {
+                problems.push(format!(
+                    "`sentiment` is neither positive nor negative: {}",
+                    value
+                ))
+            }
+            _ => {}
+        }
+    }
+    if object.is_empty() {
+        problems.push(String::from("no fields"));
+    }
+    problems
+}
+
+/// What is wrong with the url of the field `name`, if anything. Tweet urls
+/// must also have the shape of the ones written
+fn url_problem(name: &str, url: &str, tweet: bool) -> Option<String> {
+    match Url::parse(url) {
+        Ok(parsed) if tweet && !is_tweet_url(&parsed) => {
+            Some(format!("`{}` isn't the url of a tweet: {}", name, url))
+        }
+        Ok(_) => None,
+        Err(e) => Some(format!("`{}` isn't a url ({}): {}", name, e, url)),
+    }
+}
+
+/// Tells whether `url` has the shape of the tweet urls of the outputs,
+/// `https://twitter.com/<username>/status/<id>`
+fn is_tweet_url(url: &Url) -> bool {
+    let segments = url.path_segments().map(|s| s.collect::<Vec<&str>>());
+    url.host_str() == Some("twitter.com")
+        && matches!(segments.as_deref(), Some([username, "status", id])
+            if !username.is_empty() && !id.is_empty() && id.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Tells whether `date` is in RFC 3339, like Twitter writes them, or else in
+/// `date_format`
+fn is_date(date: &str, date_format: Option<&str>) -> bool {
+    match date_format {
+        None => DateTime::parse_from_rfc3339(date).is_ok(),
+        Some(format) => {
+            DateTime::parse_from_str(date, format).is_ok()
+                || NaiveDateTime::parse_from_str(date, format).is_ok()
+                || NaiveDate::parse_from_str(date, format).is_ok()
+        }
+    }
+}
+
+/// Scores of the tweets of an output written with `--fields` including
+/// `score`, and whether they are positive, from their `sentiment` if written
+/// or else from the score
+fn read_scores(path: &std::path::Path) -> Result<Vec<(f64, bool)>, String> {
+    if !path.is_file() {
+        return Err(format!("🙅 `{}` doesn't exist", path.display()));
+    }
+    let contents = read_output(path).map_err(|e| e.to_string())?;
+    let objects = match serde_json::from_str::<Value>(&contents) {
+        Ok(Value::Array(objects)) => objects,
+        Ok(Value::Object(mut envelope)) => match envelope.remove("tweets") {
+            Some(Value::Array(objects)) => objects,
+            _ => Vec::new(),
+        },
+        _ => contents
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect(),
+    };
+    let scores = objects
+        .iter()
+        .filter_map(|object| {
+            let score = object.get("score")?.as_f64()?;
+            let positive = match object.get("sentiment").and_then(Value::as_str) {
+                Some(sentiment) => sentiment == "positive",
+                None => score >= 0.5,
+            };
+            Some((score, positive))
+        })
+        .collect::<Vec<(f64, bool)>>();
+    if scores.is_empty() {
+        return Err(format!(
+            "🙅 `{}` has no scores, write it with `--fields` including `score`",
+            path.display()
+        ));
+    }
+    Ok(scores)
+}
+
+/// Classifies `sentences`, or the lines of the standard input, with the model
+/// of the flags and prints a table of their sentiment
+async fn try_sentences(
+    sentences: &[String],
+    args: &Arguments,
+    color: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let sentences = if sentences.is_empty() {
+        std::io::stdin()
+            .lock()
+            .lines()
+            .collect::<std::io::Result<Vec<String>>>()?
+            .into_iter()
+            .filter(|line| !line.trim().is_empty())
+            .collect()
+    } else {
+        sentences.to_vec()
+    };
+    if sentences.is_empty() {
+        Err(String::from(
+            "🙅 There are no sentences to classify, give them as arguments or one per line on the standard input",
+        ))?;
+    }
+    let mut classifier = LazyClassifier::new(args)?;
+    let sentiments = classifier.predict(sentences.clone()).await?;
+
+    let locale = resolve_locale(args.locale.as_deref());
+    let mut table = Table::new();
+    table
+        .load_preset(UTF8_FULL)
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(vec!["Score", "Sentiment", "Sentence"]);
+    for (sentence, sentiment) in sentences.iter().zip(sentiments) {
+        let (score, polarity) = match sentiment {
+            Ok(sentiment) => {
+                let (polarity, fg) = match sentiment.polarity {
+                    SentimentPolarity::Positive => ("positive", Color::Green),
+                    SentimentPolarity::Negative => ("negative", Color::Red),
+                };
+                let score = Cell::new(locale.decimal(happiness(&sentiment)));
+                (
+                    if color { score.fg(fg) } else { score },
+                    polarity.to_string(),
+                )
+            }
+            Err(reason) => (Cell::new("-"), format!("failed: {}", reason)),
+        };
+        table.add_row(vec![score, Cell::new(polarity), Cell::new(sentence)]);
+    }
+    println!("{}", table);
+    Ok(())
+}
+
+/// Prints the search operators and the query the flags build, running the
+/// same `QueryBuilder` as a search so the examples can't drift from it
+fn print_operators() -> Result<(), Box<dyn std::error::Error>> {
+    let mut table = Table::new();
+    table
+        .load_preset(UTF8_FULL)
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(vec!["Operator", "Matches", "Example"]);
+    for (operator, description, example) in SEARCH_OPERATORS {
+        table.add_row(vec![operator, description, example]);
+    }
+    println!("{}", table);
+    println!("The flags below add operators to the term:");
+    let paella = || QueryBuilder::new("paella");
+    let examples = [
+        ("--any \"valencia madrid\"", paella().any("valencia madrid")),
+        ("--all \"rice tasty\"", paella().all("rice tasty")),
+        ("--lang es", paella().lang("es")),
+        ("--hashtag food", paella().hashtag("food")),
+        ("--near -0.37,39.47,10km", paella().near("-0.37,39.47,10km")),
+        ("--no-quotes", paella().exclude(NON_ORIGINAL_KINDS[2])),
+        (
+            "--originals-only",
+            NON_ORIGINAL_KINDS
+                .into_iter()
+                .fold(paella(), QueryBuilder::exclude),
+        ),
+    ];
+    for (flag, query) in examples {
+        println!("  paella {:<24} searches `{}`", flag, query.build()?);
+    }
+    println!(
+        "Queries are limited to {} characters. More info: https://developer.twitter.com/en/docs/twitter-api/tweets/search/integrate/build-a-query",
+        MAX_QUERY_LENGTH
+    );
+    Ok(())
+}
+
+/// Prints `head` tweets from the start of the file, or `tail` from its end
+fn peek(
+    path: &std::path::Path,
+    head: usize,
+    tail: Option<usize>,
+    color: bool,
+    locale: Locale,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let tweets = read_tweets_file(path)?;
+    let shown = match tail {
+        Some(tail) => &tweets[tweets.len().saturating_sub(tail)..],
+        None => &tweets[..head.min(tweets.len())],
+    };
+    if shown.is_empty() {
+        println!("No tweets in {}", path.display());
+        return Ok(());
+    }
+
+    println!(
+        "{}",
+        tweets_to_table(&shown.iter().collect::<Vec<&HappyTweet>>(), color, locale)
+    );
+    println!(
+        "{} of {} tweets in {}",
+        locale.number(shown.len()),
+        locale.number(tweets.len()),
+        path.display()
+    );
+    Ok(())
+}
+
+/// Writes the tweets of all the `files` to `output`, keeping one tweet per url
+/// sorted from the newest to the oldest
+fn merge(
+    files: &[std::path::PathBuf],
+    output: &std::path::Path,
+    format: OutputFormat,
+    threshold: Option<f64>,
+    overwrite: bool,
+    locale: Locale,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut tweets: Vec<HappyTweet> = Vec::new();
+    // Position in `tweets` of the tweet of each url
+    let mut kept: HashMap<String, usize> = HashMap::new();
+    let mut read = 0;
+    for file in files {
+        let file_tweets = read_tweets_file(file)?;
+        read += file_tweets.len();
+        for tweet in file_tweets {
+            match kept.get(&tweet.tweet.url) {
+                Some(&i) => tweets[i]
+                    .tweet
+                    .add_matched_terms(&tweet.tweet.matched_terms),
+                None => {
+                    kept.insert(tweet.tweet.url.clone(), tweets.len());
+                    tweets.push(tweet);
+                }
+            }
+        }
+    }
+    if let Some(threshold) = threshold {
+        tweets = dedup_fuzzy(
+            tweets,
+            threshold,
+            |tweet| &tweet.tweet.content,
+            HappyTweet::chronological,
+        );
+    }
+    tweets.sort_by(|a, b| b.chronological(a));
+
+    let options = WriteOptions {
+        overwrite,
+        envelope: None,
+        flush_every: 0,
+        recover: false,
+        color: true,
+        locale,
+        fields: Vec::new(),
+        encoding: OutputEncoding::Utf8,
+        indent: String::from("  "),
+        next_token: None,
+    };
+    let tweets = tweets.iter().collect::<Vec<&HappyTweet>>();
+    let total = write_tweets(&tweets, output, format, &options)?;
+
+    println!(
+        "🧹 Merged {} files into {}: {} tweets, {} duplicates dropped",
+        files.len(),
+        output.display(),
+        total,
+        read - tweets.len()
+    );
+
+    Ok(())
+}
+
+/// Removes from `path` the tweets that no longer exist, looking them up in
+/// batches of 100 within the rate limit of `args`. The output is written back
+/// in its format, compression and encoding
+async fn prune(
+    path: &std::path::Path,
+    client: &reqwest::Client,
+    bearer: &str,
+    args: &Arguments,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !path.exists() {
+        Err(format!("🙅 `{}` doesn't exist", path.display()))?;
+    }
+    if path.is_dir() {
+        Err(format!(
+            "🙅 `{}` is a directory, pass the output file to prune",
+            path.display()
+        ))?;
+    }
+    let format = OutputFormat::from_path(path)
+        .or_else(|| existing_tweets_format(path))
+        .unwrap_or(OutputFormat::Json);
+    let (tweets, envelope, next_token) = match format {
+        OutputFormat::Json => match read_output_file(path)? {
+            OutputFile::Envelope(envelope) => (envelope.tweets, Some(envelope.term), None),
+            OutputFile::Tweets(tweets) => (tweets, None, None),
+        },
+        OutputFormat::Ndjson => {
+            let (tweets, next_token) = read_ndjson(path)?;
+            (tweets, None, next_token)
+        }
+        OutputFormat::Markdown | OutputFormat::Table => Err(format!(
+            "🙅 `{}` isn't JSON or NDJSON, only outputs of tweets can be pruned",
+            path.display()
+        ))?,
+    };
+    let options = WriteOptions {
+        overwrite: true,
+        envelope,
+        flush_every: 0,
+        recover: false,
+        color: true,
+        locale: resolve_locale(args.locale.as_deref()),
+        fields: Vec::new(),
+        encoding: OutputEncoding::detect(path)?,
+        indent: parse_indent(&args.indent)?,
+        next_token,
+    };
+    let ids = tweets.iter().map(HappyTweet::id).collect::<Vec<&str>>();
+
+    let limiter = RateLimiter::from_args(args)?;
+    let mut deleted: HashSet<String> = HashSet::new();
+    for batch in ids.chunks(MAX_RESULTS) {
+        let url = Url::parse_with_params(&api_url("/2/tweets"), &[("ids", batch.join(","))])?;
+        let res = get_respecting_rate_limit(client, &limiter, url, bearer).await?;
+        if !res.status().is_success() {
+            Err(format!(
+                "🙅 Couldn't look up the tweets, Twitter answered {}",
+                res.status()
+            ))?;
+        }
+        let lookup = res.json::<TwitterApiLookupResponse>().await?;
+        deleted.extend(
+            lookup
+                .errors
+                .into_iter()
+                .filter(|error| error.error_type == NOT_FOUND_ERROR)
+                .map(|error| error.resource_id),
+        );
+    }
+
+    let total = tweets.len();
+    let tweets = tweets
+        .into_iter()
+        .filter(|tweet| !deleted.contains(tweet.id()))
+        .collect::<Vec<HappyTweet>>();
+    let pruned = total - tweets.len();
+    let tweets = tweets.iter().collect::<Vec<&HappyTweet>>();
+    let left = write_tweets(&tweets, path, format, &options)?;
+
+    println!(
+        "✂️  Pruned {} deleted tweets from {}, {} tweets left",
+        pruned,
+        path.display(),
+        left
+    );
+
+    Ok(())
+}
+
+/// Token bucket shared by every request to the Twitter API, so concurrent
+/// requests never go over the rate limit. Tokens refill steadily over the
+/// 15 minutes window, and the bucket shrinks to the limit Twitter reports
+#[derive(Clone)]
+struct RateLimiter {
+    bucket: Arc<Mutex<Bucket>>,
+    retry_budget: RetryBudget,
+}
+
+/// Time all the retries of a run may spend waiting, from `--retry-budget`
+#[derive(Clone)]
+struct RetryBudget {
+    limit: Option<Duration>,
+    spent: Arc<Mutex<Duration>>,
+}
+
+impl RetryBudget {
+    fn new(limit: Option<Duration>) -> RetryBudget {
+        RetryBudget {
+            limit,
+            spent: Arc::new(Mutex::new(Duration::ZERO)),
+        }
+    }
+
+    /// Waits `wait` before retrying `what`, or fails without waiting when the
+    /// wait would go over the budget
+    async fn wait(&self, wait: Duration, what: &str) -> Result<(), String> {
+        {
+            let mut spent = self.spent.lock().expect("the retry budget is poisoned");
+            if let Some(limit) = self.limit.filter(|&limit| *spent + wait > limit) {
+                return Err(format!(
+                    "🙅 Giving up on {}: waiting {} more seconds would go over the retry budget of {} seconds, {} of them already spent",
+                    what,
+                    wait.as_secs(),
+                    limit.as_secs(),
+                    spent.as_secs()
+                ));
+            }
+            *spent += wait;
+        }
+        time::sleep(wait).await;
+        Ok(())
+    }
+}
+
+struct Bucket {
+    /// Requests allowed per window, at most the configured ones
+    capacity: f64,
+    max_capacity: f64,
+    tokens: f64,
+    refilled: Instant,
+    min_interval: Duration,
+    /// When the last token was taken
+    taken: Option<Instant>,
+}
+
+impl RateLimiter {
+    /// Limiter of `--rate-limit`, `--min-interval` and `--retry-budget`
+    fn from_args(args: &Arguments) -> Result<RateLimiter, String> {
+        Ok(RateLimiter::new(
+            args.rate_limit,
+            Duration::from_millis(args.min_interval),
+            args.retry_budget.as_deref().map(parse_window).transpose()?,
+        ))
+    }
+
+    fn new(capacity: u32, min_interval: Duration, retry_budget: Option<Duration>) -> RateLimiter {
+        let capacity = f64::from(capacity.max(1));
+        RateLimiter {
+            bucket: Arc::new(Mutex::new(Bucket {
+                capacity,
+                max_capacity: capacity,
+                tokens: capacity,
+                refilled: Instant::now(),
+                min_interval,
+                taken: None,
+            })),
+            retry_budget: RetryBudget::new(retry_budget),
+        }
+    }
+
+    /// Waits until a request can be sent, `min_interval` after the previous
+    /// one at least, and takes its token
+    async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut bucket = self.bucket.lock().expect("the rate limiter is poisoned");
+                let rate = bucket.capacity / RATE_LIMIT_WINDOW.as_secs_f64();
+                bucket.tokens = (bucket.tokens + bucket.refilled.elapsed().as_secs_f64() * rate)
+                    .min(bucket.capacity);
+                bucket.refilled = Instant::now();
+                let spaced = bucket
+                    .taken
+                    .map(|taken| bucket.min_interval.saturating_sub(taken.elapsed()))
+                    .unwrap_or_default();
+                if bucket.tokens >= 1.0 && spaced.is_zero() {
+                    bucket.tokens -= 1.0;
+                    bucket.taken = Some(Instant::now());
+                    return;
+                }
+                Duration::from_secs_f64((1.0 - bucket.tokens).max(0.0) / rate).max(spaced)
+            };
+            time::sleep(wait).await;
+        }
+    }
+
+    /// Resizes the bucket from the `x-rate-limit-*` headers of a response
+    fn update(&self, headers: &HeaderMap) {
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse::<f64>().ok())
+        };
+        let mut bucket = self.bucket.lock().expect("the rate limiter is poisoned");
+        if let Some(limit) = header("x-rate-limit-limit") {
+            bucket.capacity = limit.clamp(1.0, bucket.max_capacity);
+        }
+        if let Some(remaining) = header("x-rate-limit-remaining") {
+            bucket.tokens = bucket.tokens.min(remaining);
+        }
+    }
+}
+
+/// Sends a GET request once the rate limiter allows it, waiting for the rate
+/// limit window to reset whenever Twitter answers with a 429 anyway, as long
+/// as the retry budget allows it
+async fn get_respecting_rate_limit(
+    client: &reqwest::Client,
+    limiter: &RateLimiter,
+    url: Url,
+    bearer: &str,
+) -> Result<reqwest::Response, String> {
+    loop {
+        limiter.acquire().await;
+        let res = client
+            .get(url.clone())
+            .header(AUTHORIZATION, bearer)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        limiter.update(res.headers());
+        if res.status() != StatusCode::TOO_MANY_REQUESTS {
+            return Ok(res);
+        }
+        let reset = res
+            .headers()
+            .get("x-rate-limit-reset")
+            .and_then(|reset| reset.to_str().ok())
+            .and_then(|reset| reset.parse::<i64>().ok())
+            .unwrap_or(0);
+        let wait = (reset - Utc::now().timestamp()).clamp(1, 15 * 60) as u64
+            + rand::thread_rng().gen_range(0..=RATE_LIMIT_JITTER);
+        println!("⏳ Rate limit reached, waiting {} seconds...", wait);
+        limiter
+            .retry_budget
+            .wait(Duration::from_secs(wait), "the rate limit")
+            .await?;
+    }
+}
+
+/// Sends GET requests to the `urls` through the rate limiter, `concurrency`
+/// at a time, and returns the responses in the order of the urls
+async fn get_all(
+    client: &reqwest::Client,
+    limiter: &RateLimiter,
+    bearer: &str,
+    urls: Vec<Url>,
+    concurrency: usize,
+) -> Result<Vec<reqwest::Response>, String> {
+    let mut responses = Vec::with_capacity(urls.len());
+    let mut urls = urls.into_iter().peekable();
+    while urls.peek().is_some() {
+        let requests = urls
+            .by_ref()
+            .take(concurrency.max(1))
+            .map(|url| {
+                let (client, limiter, bearer) =
+                    (client.clone(), limiter.clone(), bearer.to_string());
+                tokio::spawn(async move {
+                    get_respecting_rate_limit(&client, &limiter, url, &bearer).await
+                })
+            })
+            .collect::<Vec<_>>();
+        for request in requests {
+            responses.push(request.await.map_err(|e| e.to_string())??);
+        }
+    }
+    Ok(responses)
+}
+
+/// Client for the Twitter API sending the `--user-agent` and the `--header`
+/// flags with every request. A `User-Agent` in `--header` takes precedence
+fn twitter_client(
+    headers: &[String],
+    user_agent: &str,
+) -> Result<reqwest::Client, Box<dyn std::error::Error>> {
+    let mut default_headers = HeaderMap::new();
+    for header in headers {
+        let (name, value) = parse_header(header)?;
+        default_headers.append(name, value);
+    }
+    Ok(reqwest::Client::builder()
+        .user_agent(user_agent)
+        .default_headers(default_headers)
+        .build()?)
+}
+
+/// Parses a `Name: Value` header of `--header`
+fn parse_header(header: &str) -> Result<(HeaderName, HeaderValue), String> {
+    let (name, value) = header
+        .split_once(':')
+        .ok_or_else(|| format!("`{}` must be written as `Name: Value`", header))?;
+    let name = HeaderName::from_bytes(name.trim().as_bytes())
+        .map_err(|_| format!("`{}` isn't a valid header name", name.trim()))?;
+    let value = HeaderValue::from_str(value.trim()).map_err(|_| {
+        format!(
+            "the value of `{}` has characters not allowed in headers",
+            name
+        )
+    })?;
+    Ok((name, value))
+}
+
+/// Bearer token from the env variable or the `--token` argument
+fn bearer_token(token: Option<String>) -> String {
+    let bearer = env::var(BEARER_ENV_TOKEN_NAME).unwrap_or_else(|_| token.unwrap_or_else(|| {
+        panic!("You need to provide a bearer token as an argument or set an env variable named `{}`. Read more: https://github.com/onmax/happy-tweet", BEARER_ENV_TOKEN_NAME);
+    }));
+
+    if !bearer.starts_with("Bearer ") {
+        format!("Bearer {}", bearer)
+    } else {
+        bearer
+    }
+}
+
+/// Posts `payload` as JSON to the webhook. With a `secret` the body is signed
+/// in the `X-Signature` header as `sha256=<hex HMAC>`. Network and server
+/// errors are retried after a `backoff` within the `retry_budget`, client
+/// errors fail at once
+async fn post_webhook(
+    client: &reqwest::Client,
+    url: &Url,
+    payload: &impl Serialize,
+    secret: Option<&str>,
+    retry_budget: &RetryBudget,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let body = serde_json::to_vec(payload)?;
+    let signature = secret.map(|secret| {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+            .expect("HMAC accepts keys of any size");
+        mac.update(&body);
+        let hex = mac
+            .finalize()
+            .into_bytes()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>();
+        format!("sha256={}", hex)
+    });
+
+    let mut attempt = 1;
+    loop {
+        let mut request = client
+            .post(url.clone())
+            .header(CONTENT_TYPE, "application/json")
+            .body(body.clone());
+        if let Some(signature) = &signature {
+            request = request.header("X-Signature", signature);
+        }
+        let error = match request.send().await {
+            Ok(res) if res.status().is_success() => return Ok(()),
+            Ok(res) if res.status().is_client_error() => Err(format!(
+                "🙅 The webhook rejected the tweets, it answered {}",
+                res.status()
+            ))?,
+            Ok(res) => format!("it answered {}", res.status()),
+            Err(error) => error.to_string(),
+        };
+        if attempt == WEBHOOK_ATTEMPTS {
+            Err(format!(
+                "🙅 Couldn't post the tweets to the webhook after {} attempts: {}",
+                attempt, error
+            ))?;
+        }
+        println!("⚠️  Posting to the webhook failed ({}), retrying", error);
+        retry_budget.wait(backoff(attempt), "the webhook").await?;
+        attempt += 1;
+    }
+}
+
+/// Delay before retrying after the failed `attempt` (from 1), with full
+/// jitter: a random duration between 0 and `BACKOFF_BASE * 2^(attempt - 1)`.
+/// Runs failing together spread their retries instead of colliding again
+fn backoff(attempt: u32) -> Duration {
+    let cap = BACKOFF_BASE * 2u32.saturating_pow(attempt.saturating_sub(1));
+    cap.mul_f64(rand::thread_rng().gen::<f64>())
+}
+
+/// How `write_tweets` writes the output
+struct WriteOptions {
+    /// Replace the file instead of appending to it
+    overwrite: bool,
+    /// Wrap the tweets in an `Envelope` with this term
+    envelope: Option<String>,
+    /// Tweets written to NDJSON between flushes, `0` to flush only at the end
+    flush_every: usize,
+    /// Back up and replace the file when it is corrupted
+    recover: bool,
+    /// Color the table output
+    color: bool,
+    /// Numbers and dates of the table and Markdown outputs
+    locale: Locale,
+    /// Only write these fields, all of them when empty
+    fields: Vec<Field>,
+    /// Text encoding of the file
+    encoding: OutputEncoding,
+    /// Indentation of JSON, from `--indent`
+    indent: String,
+    /// Pagination token kept on the last line written to NDJSON, for
+    /// `--resume`
+    next_token: Option<String>,
+}
+
+/// Writes the tweets to `path` in the given format. Unless `overwrite` is set,
+/// the tweets already in a JSON or NDJSON file are kept avoiding duplicates.
+/// Markdown is only meant to be read, so it is always replaced. Returns the
+/// number of tweets written
+fn write_tweets(
+    tweets: &[&HappyTweet],
+    path: &std::path::Path,
+    format: OutputFormat,
+    options: &WriteOptions,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    if format == OutputFormat::Table && path != std::path::Path::new(STDOUT_PATH) {
+        Err(format!(
+            "🙅 The table format is only meant for the terminal, use JSON, NDJSON or Markdown to write `{}`",
+            path.display()
+        ))?;
+    }
+    check_existing_format(path, format, options)?;
+    if !options.fields.is_empty() {
+        return write_fields(tweets, path, format, options);
+    }
+    if format == OutputFormat::Ndjson {
+        return write_ndjson(tweets, path, options);
+    }
+
+    // check if files exists and appends to the array tweets
+    let existing_tweets = if format == OutputFormat::Json && path.exists() && !options.overwrite {
+        match read_output_file(path) {
+            Ok(existing) => existing.into_tweets(),
+            Err(_) if options.recover && is_corrupted(path, format) => {
+                back_up_corrupted(path)?;
+                Vec::new()
+            }
+            Err(error) => Err(error)?,
+        }
+    } else {
+        Vec::new()
+    };
+    // A tweet found again by the search of another term keeps the terms of
+    // both. The copy is made through JSON, which doesn't hold the sentiment,
+    // since only JSON files are appended to
+    let mut rematched = HashMap::new();
+    for existing_tweet in &existing_tweets {
+        if let Some(tweet) = tweets.iter().find(|tweet| **tweet == existing_tweet) {
+            let mut copy: HappyTweet = serde_json::from_value(serde_json::to_value(tweet)?)?;
+            copy.tweet
+                .add_matched_terms(&existing_tweet.tweet.matched_terms);
+            if copy.tweet.matched_terms.len() > tweet.tweet.matched_terms.len() {
+                rematched.insert(existing_tweet.tweet.url.as_str(), copy);
+            }
+        }
+    }
+    let mut tweets = tweets
+        .iter()
+        .map(|tweet| rematched.get(tweet.tweet.url.as_str()).unwrap_or(tweet))
+        .collect::<Vec<&HappyTweet>>();
+    for existing_tweet in &existing_tweets {
+        if !tweets.contains(&existing_tweet) {
+            tweets.push(existing_tweet);
+        }
+    }
+
+    // write results
+    let total = tweets.len();
+    let mut writer = OutputWriter::create(path, false, options.encoding)?;
+    match (format, &options.envelope) {
+        (OutputFormat::Json, Some(term)) => to_writer_indented(
+            &mut writer,
+            &Envelope {
+                schema_version: SCHEMA_VERSION,
+                term: term.to_string(),
+                generated_at: Utc::now().to_rfc3339(),
+                tweets,
+            },
+            &options.indent,
+        )?,
+        (OutputFormat::Json, None) => to_writer_indented(&mut writer, &tweets, &options.indent)?,
+        (OutputFormat::Markdown, _) => {
+            writer.write_all(tweets_to_markdown(&tweets, options.locale).as_bytes())?
+        }
+        (OutputFormat::Table, _) => writeln!(
+            writer,
+            "{}",
+            tweets_to_table(&tweets, options.color, options.locale)
+        )?,
+        (OutputFormat::Ndjson, _) => unreachable!("NDJSON is written by write_ndjson"),
+    };
+    writer.finish()?;
+
+    Ok(total)
+}
+
+/// Writes only the `options.fields` of the tweets, as flat objects in JSON and
+/// NDJSON and as columns in Markdown and the table. The file is replaced since
+/// it can't be read back as tweets
+fn write_fields(
+    tweets: &[&HappyTweet],
+    path: &std::path::Path,
+    format: OutputFormat,
+    options: &WriteOptions,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let fields = &options.fields;
+    let objects = || {
+        tweets.iter().map(|&tweet| {
+            fields
+                .iter()
+                .map(|field| (field.key().to_string(), field.value(tweet)))
+                .collect::<serde_json::Map<String, Value>>()
+        })
+    };
+    let mut writer = OutputWriter::create(path, false, options.encoding)?;
+    match (format, &options.envelope) {
+        (OutputFormat::Json, Some(term)) => to_writer_indented(
+            &mut writer,
+            &Envelope {
+                schema_version: SCHEMA_VERSION,
+                term: term.to_string(),
+                generated_at: Utc::now().to_rfc3339(),
+                tweets: objects().collect(),
+            },
+            &options.indent,
+        )?,
+        (OutputFormat::Json, None) => {
+            to_writer_indented(&mut writer, &objects().collect::<Vec<_>>(), &options.indent)?
+        }
+        (OutputFormat::Ndjson, _) => {
+            for object in objects() {
+                serde_json::to_writer(&mut writer, &object)?;
+                writer.write_all(b"\n")?;
+            }
+        }
+        (OutputFormat::Markdown, _) => {
+            let row = |cells: Vec<String>| format!("| {} |\n", cells.join(" | "));
+            writer.write_all(b"# Happy tweets\n\n")?;
+            writer.write_all(
+                row(fields.iter().map(|field| field.key().to_string()).collect()).as_bytes(),
+            )?;
+            writer
+                .write_all(row(fields.iter().map(|_| String::from("---")).collect()).as_bytes())?;
+            for tweet in tweets {
+                let cells = fields
+                    .iter()
+                    .map(|field| {
+                        field
+                            .text(tweet, options.locale)
+                            .replace('\n', " ")
+                            .replace('|', "\\|")
+                    })
+                    .collect();
+                writer.write_all(row(cells).as_bytes())?;
+            }
+        }
+        (OutputFormat::Table, _) => {
+            let mut table = Table::new();
+            table
+                .load_preset(UTF8_FULL)
+                .set_content_arrangement(ContentArrangement::Dynamic)
+                .set_header(fields.iter().map(|field| field.key()));
+            for tweet in tweets {
+                table.add_row(fields.iter().map(|field| {
+                    let text = field.text(tweet, options.locale).replace('\n', " ");
+                    truncate_chars(&text, TABLE_CONTENT_LIMIT)
+                }));
+            }
+            writeln!(writer, "{}", table)?
+        }
+    };
+    writer.finish()?;
+
+    Ok(tweets.len())
+}
+
+/// Appends the tweets missing from an NDJSON file, one per line, or replaces
+/// it with `overwrite`. Lines are buffered and flushed every
+/// `options.flush_every` tweets so long runs reach the disk steadily
+fn write_ndjson(
+    tweets: &[&HappyTweet],
+    path: &std::path::Path,
+    options: &WriteOptions,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let (existing, existing_token) = if path.exists() && !options.overwrite {
+        match read_ndjson(path) {
+            Ok(existing) => existing,
+            Err(_) if options.recover && is_corrupted(path, OutputFormat::Ndjson) => {
+                back_up_corrupted(path)?;
+                (Vec::new(), None)
+            }
+            Err(error) => Err(error)?,
+        }
+    } else {
+        (Vec::new(), None)
+    };
+    let mut writer = OutputWriter::create(path, !options.overwrite, options.encoding)?;
+    let new = tweets
+        .iter()
+        .filter(|tweet| !existing.contains(tweet))
+        .collect::<Vec<_>>();
+    let mut added = 0;
+    for tweet in &new {
+        serde_json::to_writer(&mut writer, tweet)?;
+        writer.write_all(b"\n")?;
+        added += 1;
+        if options.flush_every > 0 && added % options.flush_every == 0 {
+            writer.flush()?;
+        }
+    }
+    // The cursor is written even when no tweet is new, so a resumed page of
+    // duplicates still moves it forward. A finished search without new tweets
+    // clears the cursor left by the previous run
+    if options.next_token.is_some() || (added == 0 && existing_token.is_some()) {
+        serde_json::to_writer(
+            &mut writer,
+            &Cursor {
+                next_token: options.next_token.clone(),
+            },
+        )?;
+        writer.write_all(b"\n")?;
+    }
+    writer.finish()?;
+
+    Ok(existing.len() + added)
+}
+
+/// Shell command given the tweets on its standard input, one JSON per line,
+/// for `--pipe`. Every line is flushed, so the command sees the tweets as they
+/// are sent
+struct Pipe {
+    command: String,
+    child: std::process::Child,
+    /// `None` once the command closed its input, like `head` does
+    stdin: Option<std::process::ChildStdin>,
+}
+
+impl Pipe {
+    fn spawn(command: &str) -> Result<Pipe, String> {
+        let mut child = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .stdin(std::process::Stdio::piped())
+            .spawn()
+            .map_err(|error| format!("🙅 Couldn't run `{}`: {}", command, error))?;
+        let stdin = child.stdin.take();
+        Ok(Pipe {
+            command: command.to_string(),
+            child,
+            stdin,
+        })
+    }
+
+    /// Tells whether the command still reads the tweets
+    fn is_open(&self) -> bool {
+        self.stdin.is_some()
+    }
+
+    /// Writes the tweet, or any JSON, as a line. The tweets sent after the
+    /// command closed its input are dropped, leaving `finish` to tell whether
+    /// it failed
+    fn send(&mut self, tweet: &impl Serialize) -> Result<(), String> {
+        let stdin = match &mut self.stdin {
+            Some(stdin) => stdin,
+            None => return Ok(()),
+        };
+        let mut line = serde_json::to_vec(tweet).map_err(|error| error.to_string())?;
+        line.push(b'\n');
+        match stdin.write_all(&line).and_then(|_| stdin.flush()) {
+            Ok(()) => Ok(()),
+            Err(error) if error.kind() == std::io::ErrorKind::BrokenPipe => {
+                self.stdin = None;
+                Ok(())
+            }
+            Err(error) => Err(format!(
+                "🙅 Couldn't give the tweets to `{}`: {}",
+                self.command, error
+            )),
+        }
+    }
+
+    /// Closes the input of the command and waits for it, failing with its
+    /// exit status unless it succeeded
+    fn finish(mut self) -> Result<(), String> {
+        self.stdin = None;
+        let status = self
+            .child
+            .wait()
+            .map_err(|error| format!("🙅 Couldn't wait for `{}`: {}", self.command, error))?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(format!("🙅 `{}` failed ({})", self.command, status))
+        }
+    }
+}
+
+/// Reads the tweets of an output file in JSON or NDJSON, guessing the format
+/// from the extension or else from the content
+fn read_tweets_file(path: &std::path::Path) -> Result<Vec<HappyTweet>, String> {
+    if !path.is_file() {
+        return Err(format!("🙅 `{}` doesn't exist", path.display()));
+    }
+    match OutputFormat::from_path(path) {
+        Some(OutputFormat::Json) => read_output_file(path).map(OutputFile::into_tweets),
+        Some(OutputFormat::Ndjson) => read_ndjson_file(path),
+        Some(format) => Err(format!(
+            "🙅 `{}` is {:?}, the tweets can't be read back from it",
+            path.display(),
+            format
+        )),
+        None => read_output_file(path)
+            .map(OutputFile::into_tweets)
+            .or_else(|error| read_ndjson_file(path).map_err(|_| error)),
+    }
+}
+
+/// Ids of the tweets of an NDJSON output for `--resume`, and the pagination
+/// token on its last line when the run writing it stopped before the end of
+/// the search
+fn read_resume(path: &std::path::Path) -> Result<(HashSet<String>, Option<String>), String> {
+    if !path.exists() {
+        return Ok((HashSet::new(), None));
+    }
+    let (tweets, next_token) = read_ndjson(path)?;
+    let ids = tweets.iter().map(|tweet| tweet.id().to_string()).collect();
+    Ok((ids, next_token))
+}
+
+/// Last line of an NDJSON output written by a search that stopped before its
+/// end, holding the pagination token `--resume` continues from. `None` marks
+/// a search that was finished since
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Cursor {
+    next_token: Option<String>,
+}
+
+/// Reads the tweets of a previous run written as NDJSON
+fn read_ndjson_file(path: &std::path::Path) -> Result<Vec<HappyTweet>, String> {
+    read_ndjson(path).map(|(tweets, _)| tweets)
+}
+
+/// Reads the tweets of an NDJSON output at once, with the pagination token of
+/// its last line. The lines of the cursors of previous runs are skipped, and
+/// tweets with a `next_token` of their own, as older runs wrote it, are read
+/// as tweets
+fn read_ndjson(path: &std::path::Path) -> Result<(Vec<HappyTweet>, Option<String>), String> {
+    let contents = read_output(path).map_err(|e| e.to_string())?;
+    let lines = contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .collect::<Vec<&str>>();
+    let next_token = lines
+        .last()
+        .and_then(|line| serde_json::from_str::<Value>(line).ok())
+        .and_then(|line| Some(line.get("next_token")?.as_str()?.to_string()));
+    lines
+        .iter()
+        .filter(|line| serde_json::from_str::<Cursor>(line).is_err())
+        .map(|line| serde_json::from_str(line))
+        .collect::<Result<Vec<HappyTweet>, _>>()
+        .map(|tweets| (tweets, next_token))
+        .map_err(|_| {
+            let reason = if is_corrupted(path, OutputFormat::Ndjson) {
+                CORRUPTED_REASON
+            } else {
+                "it isn't a file of JSON tweets, one per line"
+            };
+            format!(
+                "🙅 Can't append the results to `{}`: {}. Use another output or `--overwrite` to replace it",
+                path.display(),
+                reason
+            )
+        })
+}
+
+/// Renders the tweets as a table fitting the width of the terminal, with the
+/// score colored by sentiment unless `color` is off
+fn tweets_to_table(tweets: &[&HappyTweet], color: bool, locale: Locale) -> Table {
+    let mut table = Table::new();
+    table
+        .load_preset(UTF8_FULL)
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(vec!["User", "Score", "Tweet", "Date"]);
+    for tweet in tweets {
+        let score = match &tweet.sentiment {
+            Some(sentiment) => {
+                let marker = if tweet.tweet.emoji_boost.is_some() {
+                    "*"
+                } else {
+                    ""
+                };
+                let cell = Cell::new(format!(
+                    "{}{}",
+                    locale.decimal(happiness(sentiment)),
+                    marker
+                ));
+                match (color, &sentiment.polarity) {
+                    (false, _) => cell,
+                    (true, SentimentPolarity::Positive) => cell.fg(Color::Green),
+                    (true, SentimentPolarity::Negative) => cell.fg(Color::Red),
+                }
+            }
+            None => Cell::new("-"),
+        };
+        table.add_row(vec![
+            Cell::new(format!("@{}", tweet.user.username)),
+            score,
+            Cell::new(truncate_chars(
+                &tweet.tweet.content.replace('\n', " "),
+                TABLE_CONTENT_LIMIT,
+            )),
+            Cell::new(locale.date(&tweet.tweet.created_at)),
+        ]);
+    }
+    table
+}
+
+/// Separators of the numbers and style of the dates of `--locale`. Dates are
+/// only restyled when they are RFC 3339, so `--date-format` always wins
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Locale {
+    decimal: char,
+    thousands: Option<char>,
+    date: Option<&'static str>,
+}
+
+impl Locale {
+    /// `C`, writing numbers and dates like the JSON outputs
+    const C: Locale = Locale {
+        decimal: '.',
+        thousands: None,
+        date: None,
+    };
+
+    const fn new(decimal: char, thousands: Option<char>, date: &'static str) -> Locale {
+        Locale {
+            decimal,
+            thousands,
+            date: Some(date),
+        }
+    }
+
+    /// Locale of `LC_ALL`, `LC_NUMERIC` or `LANG`, or `C` when it isn't known
+    fn system() -> Locale {
+        ["LC_ALL", "LC_NUMERIC", "LANG"]
+            .iter()
+            .filter_map(|name| env::var(name).ok())
+            .find(|value| !value.is_empty())
+            .and_then(|value| parse_locale(&value).ok())
+            .unwrap_or(Locale::C)
+    }
+
+    fn number(self, n: usize) -> String {
+        let digits = n.to_string();
+        let separator = match self.thousands {
+            Some(separator) => separator,
+            None => return digits,
+        };
+        let mut number = String::new();
+        for (i, digit) in digits.chars().enumerate() {
+            if i > 0 && (digits.len() - i).is_multiple_of(3) {
+                number.push(separator);
+            }
+            number.push(digit);
+        }
+        number
+    }
+
+    /// Score with 2 decimals
+    fn decimal(self, x: f64) -> String {
+        format!("{:.2}", x).replace('.', &self.decimal.to_string())
+    }
+
+    fn date(self, created_at: &str) -> String {
+        match (self.date, DateTime::parse_from_rfc3339(created_at)) {
+            (Some(format), Ok(date)) => date.format(format).to_string(),
+            _ => created_at.to_string(),
+        }
+    }
+}
+
+/// Parses a locale like `de_DE.UTF-8`, `pt-BR` or `C`. Besides `C` and
+/// `POSIX`, only the languages of `LOCALES` are known
+fn parse_locale(locale: &str) -> Result<Locale, String> {
+    let name = locale
+        .split(['.', '@'])
+        .next()
+        .unwrap_or_default()
+        .replace('-', "_");
+    if name == "C" || name == "POSIX" {
+        return Ok(Locale::C);
+    }
+    let language = name.split('_').next().unwrap_or_default().to_lowercase();
+    let region = name.split('_').nth(1).map(str::to_uppercase);
+    let full = match region {
+        Some(region) => format!("{}_{}", language, region),
+        None => language.clone(),
+    };
+    LOCALES
+        .iter()
+        .find(|(name, _)| *name == full)
+        .or_else(|| LOCALES.iter().find(|(name, _)| *name == language))
+        .map(|&(_, locale)| locale)
+        .ok_or_else(|| {
+            let known = LOCALES.iter().map(|(name, _)| *name).collect::<Vec<&str>>();
+            format!(
+                "`{}` isn't a known locale, use `C` or one of {}",
+                locale,
+                known.join(", ")
+            )
+        })
+}
+
+/// The `--locale`, validated by clap, or else the one of the system
+fn resolve_locale(locale: Option<&str>) -> Locale {
+    locale
+        .and_then(|locale| parse_locale(locale).ok())
+        .unwrap_or_else(Locale::system)
+}
+
+/// Renders the tweets as a Markdown list of quotes, ready to be pasted in a
+/// chat or a document
+fn tweets_to_markdown(tweets: &[&HappyTweet], locale: Locale) -> String {
+    let mut markdown = String::from("# Happy tweets\n");
+    for tweet in tweets {
+        let quote = tweet
+            .tweet
+            .content
+            .lines()
+            .map(|line| format!("> {}", line))
+            .collect::<Vec<String>>()
+            .join("\n");
+        markdown.push_str(&format!(
+            "\n**[@{}]({})** · {}\n\n{}\n",
+            tweet.user.username,
+            tweet.tweet.url,
+            locale.date(&tweet.tweet.created_at),
+            quote
+        ));
+    }
+    markdown
+}
+
+/// Keeps the parts of the API responses belonging to the kept tweets, so they
+/// can be written with the Twitter schema
+fn raw_response(data: &TwitterApiResponse, tweets: &[HappyTweet]) -> TwitterApiResponse {
+    let ids = tweets.iter().map(HappyTweet::id).collect::<HashSet<&str>>();
+    let kept = data
+        .data
+        .iter()
+        .filter(|tweet| ids.contains(tweet.id.as_str()))
+        .cloned()
+        .collect::<Vec<TwitterApiResponseData>>();
+    let authors = kept
+        .iter()
+        .map(|tweet| tweet.author_id.as_str())
+        .collect::<HashSet<&str>>();
+    let mut users: Vec<TwitterApiResponseUser> = Vec::new();
+    for user in &data.includes.users {
+        if authors.contains(user.id.as_str()) && !users.iter().any(|u| u.id == user.id) {
+            users.push(user.clone());
+        }
+    }
+    TwitterApiResponse {
+        meta: TwitterApiResponseMeta {
+            result_count: kept.len() as u16,
+            ..data.meta.clone()
+        },
+        data: kept,
+        includes: TwitterApiResponseIncludes {
+            users,
+            tweets: Vec::new(),
+        },
+    }
+}
+
+/// Writes `value` as JSON indented with `indent` instead of the 2 spaces of
+/// `serde_json::to_writer_pretty`
+fn to_writer_indented<W: Write, T: Serialize>(
+    writer: W,
+    value: &T,
+    indent: &str,
+) -> serde_json::Result<()> {
+    let formatter = PrettyFormatter::with_indent(indent.as_bytes());
+    value.serialize(&mut serde_json::Serializer::with_formatter(
+        writer, formatter,
+    ))
+}
+
+/// Parses `--indent`, a number of spaces or `tab`
+fn parse_indent(indent: &str) -> Result<String, String> {
+    match indent {
+        "tab" => Ok(String::from("\t")),
+        spaces => match spaces.parse::<usize>() {
+            Ok(spaces) if spaces <= MAX_INDENT => Ok(" ".repeat(spaces)),
+            _ => Err(format!(
+                "the indent must be `tab` or a number of spaces up to {}",
+                MAX_INDENT
+            )),
+        },
+    }
+}
+
+/// Writes `raw` with the Twitter schema. Unless `overwrite` is set, it is
+/// merged with the tweets and users already in the file. Returns the number
+/// of tweets written
+fn write_raw(
+    raw: &TwitterApiResponse,
+    path: &std::path::Path,
+    overwrite: bool,
+    recover: bool,
+    encoding: OutputEncoding,
+    indent: &str,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut raw = raw.clone();
+    if path.exists() && !overwrite && recover && is_corrupted(path, OutputFormat::Json) {
+        back_up_corrupted(path)?;
+    }
+    if path.exists() && !overwrite {
+        let contents = read_output(path)?;
+        if !contents.trim().is_empty() {
+            let existing: TwitterApiResponse = serde_json::from_str(&contents).map_err(|_| {
+                let reason = if serde_json::from_str::<Value>(&contents).is_err() {
+                    CORRUPTED_REASON
+                } else {
+                    "it doesn't have the Twitter schema"
+                };
+                format!(
+                    "🙅 Can't append the results to `{}`: {}. Use another output or `--overwrite` to replace it",
+                    path.display(),
+                    reason
+                )
+            })?;
+            for tweet in existing.data {
+                if !raw.data.iter().any(|t| t.id == tweet.id) {
+                    raw.data.push(tweet);
+                }
+            }
+            for user in existing.includes.users {
+                if !raw.includes.users.iter().any(|u| u.id == user.id) {
+                    raw.includes.users.push(user);
+                }
+            }
+            raw.meta.result_count = raw.data.len() as u16;
+        }
+    }
+
+    let mut writer = OutputWriter::create(path, false, encoding)?;
+    to_writer_indented(&mut writer, &raw, indent)?;
+    writer.finish()?;
+    Ok(raw.data.len())
+}
+
+/// Paths and formats of the outputs. The format comes from the `--format` in
+/// the same position or else the extension, a directory gets a default file
+/// name inside it and `--compress` adds its extension to the files
+fn resolve_outputs(args: &Arguments) -> Vec<(std::path::PathBuf, OutputFormat)> {
+    args.output
+        .iter()
+        .enumerate()
+        .map(|(i, path)| {
+            let format = args
+                .format
+                .get(i)
+                .copied()
+                .unwrap_or_else(|| OutputFormat::from_path(path).unwrap_or(OutputFormat::Json));
+            let path = if path.is_dir() {
+                path.join(format!("{}.{}", DEFAULT_OUTPUT_NAME, format.extension()))
+            } else {
+                path.clone()
+            };
+            let path = match args.compress {
+                Some(compression)
+                    if path != std::path::Path::new(STDOUT_PATH)
+                        && Compression::from_path(&path) != Some(compression) =>
+                {
+                    std::path::PathBuf::from(format!(
+                        "{}.{}",
+                        path.display(),
+                        compression.extension()
+                    ))
+                }
+                _ => path,
+            };
+            (path, format)
+        })
+        .collect()
+}
+
+/// Fingerprints of the content of the tweets written to an output, kept in a
+/// `<output>.fingerprints` file of `url<TAB>fingerprint` lines so the next
+/// runs can drop repeated content without reading the output
+struct Fingerprints {
+    path: std::path::PathBuf,
+    urls: HashMap<String, String>,
+    fingerprints: HashSet<String>,
+}
+
+impl Fingerprints {
+    /// Reads the fingerprints of an output. With a `window`, the ones of tweets
+    /// posted before it are forgotten
+    fn load(output: &std::path::Path, window: Option<Duration>) -> Result<Fingerprints, String> {
+        let path = std::path::PathBuf::from(format!("{}.fingerprints", output.display()));
+        let mut urls = HashMap::new();
+        if path.exists() {
+            let contents = std::fs::read_to_string(&path).map_err(|e| e.to_string())?;
+            for line in contents.lines().filter(|line| !line.is_empty()) {
+                let (url, fingerprint) = line.split_once('\t').ok_or_else(|| {
+                    format!(
+                        "🙅 `{}` is not a fingerprints file, remove it to start a new one",
+                        path.display()
+                    )
+                })?;
+                urls.insert(url.to_string(), fingerprint.to_string());
+            }
+        }
+        if let Some(window) = window {
+            let oldest =
+                Utc::now() - chrono::Duration::from_std(window).map_err(|e| e.to_string())?;
+            urls.retain(|url, _| {
+                let id = url.rsplit('/').next().unwrap_or_default();
+                posted_at(id).is_none_or(|posted_at| posted_at >= oldest)
+            });
+        }
+        let fingerprints = urls.values().cloned().collect();
+        Ok(Fingerprints {
+            path,
+            urls,
+            fingerprints,
+        })
+    }
+
+    fn contains(&self, fingerprint: &str) -> bool {
+        self.fingerprints.contains(fingerprint)
+    }
+
+    fn insert(&mut self, url: &str, content: &str) {
+        let fingerprint = fingerprint(content);
+        self.fingerprints.insert(fingerprint.clone());
+        self.urls.insert(url.to_string(), fingerprint);
+    }
+
+    /// Replaces the fingerprints with `write_atomically`, so an interrupted
+    /// run never leaves them half written. The lines are sorted by url so the
+    /// file only changes when the fingerprints do
+    fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let mut urls = self.urls.iter().collect::<Vec<(&String, &String)>>();
+        urls.sort();
+        let mut lines = Vec::new();
+        for (url, fingerprint) in urls {
+            writeln!(lines, "{}\t{}", url, fingerprint)?;
+        }
+        write_atomically(&self.path, &lines)?;
+        Ok(())
+    }
+}
+
+/// Stable hash of the content of a tweet, ignoring case, spacing and links
+/// since Twitter shortens every link differently
+fn fingerprint(content: &str) -> String {
+    let normalized = content
+        .split_whitespace()
+        .filter(|token| !token.contains("://"))
+        .map(str::to_lowercase)
+        .collect::<Vec<String>>()
+        .join(" ");
+    text_hash(&normalized)
+}
+
+/// Path for one sentiment when splitting the output, e.g. `tweets.json`
+/// becomes `tweets.positive.json`
+fn split_output_path(path: &std::path::Path, sentiment: &str) -> std::path::PathBuf {
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("json");
+    path.with_extension(format!("{}.{}", sentiment, extension))
+}
+
+/// Fields requested for every tweet and author. Optional fields are only
+/// requested when a flag needs them, to keep responses small
+struct SearchFields {
+    tweet: Vec<&'static str>,
+    user: Vec<&'static str>,
+    expansions: Vec<&'static str>,
+}
+
+impl SearchFields {
+    fn new(args: &Arguments) -> SearchFields {
+        let mut tweet = vec!["created_at", "entities", "context_annotations"];
+        let mut user = vec!["profile_image_url"];
+        let mut expansions = vec!["author_id"];
+        if args.author_details {
+            user.extend(["description", "location"]);
+        } else if args.require_bio {
+            user.push("description");
+        }
+        if args.author_badges {
+            user.push("verified_type");
+        }
+        if args.translate {
+            tweet.push("lang");
+        }
+        if args.include_source_app {
+            tweet.push("source");
+        }
+        if args.no_sensitive {
+            tweet.push("possibly_sensitive");
+        }
+        if args.collapse_retweets || args.with_context {
+            tweet.push("referenced_tweets");
+        }
+        if args.collapse_retweets {
+            expansions.extend(["referenced_tweets.id", "referenced_tweets.id.author_id"]);
+        }
+        SearchFields {
+            tweet,
+            user,
+            expansions,
+        }
+    }
+}
+
+/// Dates the search is limited to
+struct TimeWindow {
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+    /// Only tweets newer than this one, from `--since-last-run`
+    since_id: Option<String>,
+}
+
+impl TimeWindow {
+    fn append_to(&self, url: &mut Url) {
+        for (name, date) in [("start_time", self.since), ("end_time", self.until)] {
+            if let Some(date) = date {
+                url.query_pairs_mut()
+                    .append_pair(name, &date.to_rfc3339_opts(SecondsFormat::Secs, true));
+            }
+        }
+        if let Some(since_id) = &self.since_id {
+            url.query_pairs_mut().append_pair("since_id", since_id);
+        }
+    }
+}
+
+fn search_url(
+    term: &str,
+    fields: &SearchFields,
+    window: &TimeWindow,
+    max_results: usize,
+    next_token: Option<&str>,
+) -> Result<Url, url::ParseError> {
+    let mut url = Url::parse_with_params(
+        &api_url("/2/tweets/search/recent"),
+        &[
+            ("max_results", max_results.to_string().as_str()),
+            ("query", term),
+            ("tweet.fields", &fields.tweet.join(",")),
+            ("expansions", &fields.expansions.join(",")),
+            ("user.fields", &fields.user.join(",")),
+        ],
+    )?;
+    window.append_to(&mut url);
+    if let Some(next_token) = next_token {
+        url.query_pairs_mut().append_pair("next_token", next_token);
+    }
+    Ok(url)
+}
+
+/// Url of `path` in the Twitter API, or in the one of `HAPPY_TWEET_API_URL`
+fn api_url(path: &str) -> String {
+    let base = env::var(API_URL_ENV_NAME).unwrap_or_else(|_| String::from(API_URL));
+    format!("{}{}", base.trim_end_matches('/'), path)
+}
+
+/// Url of a page of the tweets mentioning the user `id`, which come with the
+/// same fields as the search
+fn mentions_url(
+    id: &str,
+    fields: &SearchFields,
+    window: &TimeWindow,
+    max_results: usize,
+    next_token: Option<&str>,
+) -> Result<Url, url::ParseError> {
+    let mut url = Url::parse_with_params(
+        &api_url(&format!("/2/users/{}/mentions", id)),
+        &[
+            ("max_results", max_results.to_string().as_str()),
+            ("tweet.fields", &fields.tweet.join(",")),
+            ("expansions", &fields.expansions.join(",")),
+            ("user.fields", &fields.user.join(",")),
+        ],
+    )?;
+    window.append_to(&mut url);
+    if let Some(next_token) = next_token {
+        url.query_pairs_mut()
+            .append_pair("pagination_token", next_token);
+    }
+    Ok(url)
+}
+
+/// Id of the user `username`, with or without the `@`, for the timelines
+async fn user_id(
+    client: &reqwest::Client,
+    limiter: &RateLimiter,
+    bearer: &str,
+    username: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let username = username.trim_start_matches('@');
+    if username.is_empty()
+        || username.len() > 15
+        || !username
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_')
+    {
+        Err(format!(
+            "🙅 `{}` isn't a username. With `--timeline` the term is the username of the account, e.g. `onmax`",
+            username
+        ))?;
+    }
+    let url = Url::parse(&api_url(&format!("/2/users/by/username/{}", username)))?;
+    let res = get_respecting_rate_limit(client, limiter, url, bearer).await?;
+    if !res.status().is_success() {
+        Err(format!(
+            "🙅 Couldn't look up @{}, Twitter answered {}",
+            username,
+            res.status()
+        ))?;
+    }
+    // Twitter answers 200 with an error when the user doesn't exist
+    let user = res.json::<Value>().await?;
+    match user.pointer("/data/id").and_then(Value::as_str) {
+        Some(id) => Ok(id.to_string()),
+        None => Err(format!("🙅 There is no user @{} on Twitter", username))?,
+    }
+}
+
+/// Size of the next page for `--adaptive-paging`, enough to reach `max_total`
+/// kept tweets if the next ones are kept as often as the `fetched` so far.
+/// Before any page the tweets are expected to be kept, so a small
+/// `max_total` doesn't pull a full page
+fn adaptive_page_size(max_total: usize, kept: usize, fetched: usize) -> usize {
+    let needed = max_total.saturating_sub(kept);
+    if fetched == 0 {
+        needed
+    } else if kept == 0 {
+        MAX_RESULTS
+    } else {
+        (needed * fetched).div_ceil(kept)
+    }
+}
+
+fn model_cache_dir() -> std::path::PathBuf {
+    match (env::var(MODEL_CACHE_ENV_NAME), env::var("HOME")) {
+        (Ok(cache_dir), _) => std::path::PathBuf::from(cache_dir),
+        (Err(_), Ok(home)) => std::path::Path::new(&home).join(".cache").join(".rustbert"),
+        (Err(_), Err(_)) => std::path::PathBuf::from(".rustbert"),
+    }
+}
+
+fn default_usage_path() -> std::path::PathBuf {
+    match env::var("HOME") {
+        Ok(home) => std::path::Path::new(&home).join(USAGE_FILE_NAME),
+        Err(_) => std::path::PathBuf::from(USAGE_FILE_NAME),
+    }
+}
+
+/// Where a run ended, for the next one to continue with `--since-last-run`
+#[derive(Debug, Serialize, Deserialize)]
+struct RunState {
+    term: String,
+    /// When the run started, so the tweets posted while it ran aren't skipped
+    started_at: String,
+    /// Newest tweet found by the run or, if it found none, by the previous ones
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    newest_id: Option<String>,
+}
+
+impl RunState {
+    fn new(
+        term: &str,
+        started_at: DateTime<Utc>,
+        newest_id: Option<String>,
+        last_run: Option<RunState>,
+    ) -> RunState {
+        RunState {
+            term: term.to_string(),
+            started_at: started_at.to_rfc3339(),
+            newest_id: newest_id.or_else(|| last_run.and_then(|last_run| last_run.newest_id)),
+        }
+    }
+
+    fn load(path: &std::path::Path, term: &str) -> Result<RunState, String> {
+        if !path.exists() {
+            return Err(format!(
+                "🙅 There is no previous run in `{}`. Run a full search once with `--state-file {}` to record where it ends, then add `--since-last-run`",
+                path.display(),
+                path.display()
+            ));
+        }
+        let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let state: RunState = serde_json::from_str(&contents)
+            .map_err(|e| format!("🙅 Can't read the state file `{}`: {}", path.display(), e))?;
+        if state.term != term {
+            return Err(format!(
+                "🙅 The state file `{}` is of a search of `{}`, use another `--state-file` for `{}`",
+                path.display(),
+                state.term,
+                term
+            ));
+        }
+        Ok(state)
+    }
+
+    /// Narrows the `window` to the tweets newer than the newest one found, or
+    /// else posted since the run started. The recent search doesn't reach
+    /// older than its last days, so a run that old searches all of them
+    fn narrow(&self, window: &mut TimeWindow, now: DateTime<Utc>) {
+        let earliest = now - chrono::Duration::days(RECENT_SEARCH_DAYS) + RECENT_SEARCH_MARGIN;
+        let newest = self.newest_id.as_deref().and_then(posted_at);
+        let started_at = DateTime::parse_from_rfc3339(&self.started_at)
+            .ok()
+            .map(|date| date.with_timezone(&Utc));
+        match (newest, started_at) {
+            (Some(newest), _) if newest >= earliest => {
+                println!("⏩ Searching the tweets newer than the last run, posted after {}", newest.to_rfc3339_opts(SecondsFormat::Secs, true));
+                window.since_id = self.newest_id.clone();
+            }
+            (None, Some(started_at)) if started_at >= earliest => {
+                println!("⏩ Searching the tweets posted since the last run, on {}", self.started_at);
+                window.since = Some(started_at);
+            }
+            _ => println!(
+                "⚠️  The last run is older than the {} days the recent search reaches, searching all of them",
+                RECENT_SEARCH_DAYS
+            ),
+        }
+    }
+
+    fn save(&self, path: &std::path::Path) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        write_atomically(path, json.as_bytes())
+            .map_err(|e| format!("🙅 Can't write the state file `{}`: {}", path.display(), e))
+    }
+}
+
+/// Writes `contents` to a temporary file moved over `path`, so a run killed
+/// while writing it leaves the previous file
+fn write_atomically(path: &std::path::Path, contents: &[u8]) -> std::io::Result<()> {
+    let temporary = std::path::PathBuf::from(format!("{}.tmp", path.display()));
+    std::fs::write(&temporary, contents)?;
+    std::fs::rename(&temporary, path)
+}
+
+/// Number of tweets pulled from the Twitter API during a month
+#[derive(Default, Debug, Serialize, Deserialize)]
+struct ApiUsage {
+    month: String,
+    tweets: usize,
+}
+
+impl ApiUsage {
+    /// Reads the usage of the current month. A file from a previous month
+    /// starts a new count
+    fn load(path: &std::path::Path) -> Result<ApiUsage, String> {
+        let month = Utc::now().format("%Y-%m").to_string();
+        let usage = if path.exists() {
+            let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+            serde_json::from_str(&contents)
+                .map_err(|e| format!("🙅 Can't read the usage file `{}`: {}", path.display(), e))?
+        } else {
+            ApiUsage::default()
+        };
+        if usage.month == month {
+            Ok(usage)
+        } else {
+            Ok(ApiUsage { month, tweets: 0 })
+        }
+    }
+
+    fn save(&self, path: &std::path::Path) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        write_atomically(path, json.as_bytes())
+            .map_err(|e| format!("🙅 Can't write the usage file `{}`: {}", path.display(), e))
+    }
+}
+
+/// Sentiments already predicted by a model, keyed by the hash of the text.
+/// The model is kept in the file so a cache of another model is never used.
+/// The hashes are sorted so the same sentiments always write the same file
+#[derive(Debug, Serialize, Deserialize)]
+struct SentimentCache {
+    #[serde(skip)]
+    path: std::path::PathBuf,
+    model: String,
+    sentiments: BTreeMap<String, Sentiment>,
+}
+
+impl SentimentCache {
+    /// Reads the cache of `model`. A cache of another model starts empty and is
+    /// replaced when saved
+    fn load(path: &std::path::Path, model: &str) -> Result<SentimentCache, String> {
+        if path.exists() {
+            let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+            let cache: SentimentCache = serde_json::from_str(&contents).map_err(|e| {
+                format!(
+                    "🙅 Can't read the sentiment cache `{}`: {}",
+                    path.display(),
+                    e
+                )
+            })?;
+            if cache.model == model {
+                return Ok(SentimentCache {
+                    path: path.to_path_buf(),
+                    ..cache
+                });
+            }
+            println!(
+                "♻️  The sentiment cache was made with another model, the tweets are classified again"
+            );
+        }
+        Ok(SentimentCache {
+            path: path.to_path_buf(),
+            model: model.to_string(),
+            sentiments: BTreeMap::new(),
+        })
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let json = serde_json::to_string(self).map_err(|e| e.to_string())?;
+        write_atomically(&self.path, json.as_bytes()).map_err(|e| {
+            format!(
+                "🙅 Can't write the sentiment cache `{}`: {}",
+                self.path.display(),
+                e
+            )
+        })
+    }
+}
+
+/// Hash of the exact text of a tweet, as classified by the model
+fn text_hash(text: &str) -> String {
+    Sha256::digest(text.as_bytes())
+        .iter()
+        .take(16)
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Search query of the term followed by the operators of the flags: the
+/// `--any` words joined with `OR`, the `--all` words, which Twitter already
+/// requires all of, the hashtags joined with `OR`, `lang:<lang>`,
+/// `point_radius:[...]` and `-is:<kind>` for every excluded kind. It prints
+/// as assembled, `build` also checks it can match tweets and fits the length
+/// Twitter accepts
+struct QueryBuilder {
+    term: String,
+    any: Vec<String>,
+    all: Vec<String>,
+    /// Hashtags with their `#`, of which tweets must have one
+    hashtags: Vec<String>,
+    lang: Option<String>,
+    /// `point_radius` operator of `--near`
+    near: Option<String>,
+    /// Kinds of tweets left out, with the flag leaving each out
+    excluded: Vec<(&'static str, &'static str)>,
+}
+
+impl QueryBuilder {
+    fn new(term: &str) -> QueryBuilder {
+        QueryBuilder {
+            term: term.to_string(),
+            any: Vec::new(),
+            all: Vec::new(),
+            hashtags: Vec::new(),
+            lang: None,
+            near: None,
+            excluded: Vec::new(),
+        }
+    }
+
+    /// Query of the term with the operators of the flags of a search
+    fn from_args(term: &str, args: &Arguments) -> QueryBuilder {
+        let mut query = QueryBuilder::new(term);
+        if let Some(any) = &args.any {
+            query = query.any(any);
+        }
+        if let Some(all) = &args.all {
+            query = query.all(all);
+        }
+        for tag in &args.hashtag {
+            query = query.hashtag(tag);
+        }
+        if let Some(lang) = &args.lang {
+            query = query.lang(lang);
+        }
+        if let Some(near) = &args.near {
+            query = query.near(near);
+        }
+        NON_ORIGINAL_KINDS
+            .into_iter()
+            .zip([args.no_retweets, args.no_replies, args.no_quotes])
+            .filter(|&(_, flag)| flag || args.originals_only)
+            .fold(query, |query, (kind, _)| query.exclude(kind))
+    }
+
+    /// Adds the space-separated `words`, of which tweets must contain one
+    fn any(mut self, words: &str) -> QueryBuilder {
+        self.any.extend(words.split_whitespace().map(String::from));
+        self
+    }
+
+    /// Adds the space-separated `words`, which tweets must all contain
+    fn all(mut self, words: &str) -> QueryBuilder {
+        self.all.extend(words.split_whitespace().map(String::from));
+        self
+    }
+
+    /// Adds a hashtag, with or without `#`, of which tweets must have one
+    fn hashtag(mut self, tag: &str) -> QueryBuilder {
+        let tag = format!("#{}", tag.trim_start_matches('#'));
+        if !self.hashtags.contains(&tag) {
+            self.hashtags.push(tag);
+        }
+        self
+    }
+
+    fn lang(mut self, lang: &str) -> QueryBuilder {
+        self.lang = Some(lang.to_string());
+        self
+    }
+
+    /// Only tweets geotagged around a `longitude,latitude,radius` point, as
+    /// checked by `validate_near`
+    fn near(mut self, point: &str) -> QueryBuilder {
+        self.near = Some(format!("point_radius:[{}]", point.replace(',', " ")));
+        self
+    }
+
+    /// Leaves out a kind of tweets of `NON_ORIGINAL_KINDS`
+    fn exclude(mut self, kind: (&'static str, &'static str)) -> QueryBuilder {
+        if !self.excluded.contains(&kind) {
+            self.excluded.push(kind);
+        }
+        self
+    }
+
+    /// The term with the words, the hashtags, the language and the point,
+    /// without the exclusions
+    fn base(&self) -> String {
+        let alternatives = |words: &[String]| match words {
+            [] => String::new(),
+            [word] => format!(" {}", word),
+            words => format!(" ({})", words.join(" OR ")),
+        };
+        let mut query = self.term.clone();
+        query.push_str(&alternatives(&self.any));
+        for word in &self.all {
+            query.push_str(&format!(" {}", word));
+        }
+        query.push_str(&alternatives(&self.hashtags));
+        if let Some(lang) = &self.lang {
+            query.push_str(&format!(" lang:{}", lang));
+        }
+        if let Some(near) = &self.near {
+            query.push_str(&format!(" {}", near));
+        }
+        query
+    }
+
+    /// Words every tweet must match: the ones of the term outside of the
+    /// groups with `OR`, whose words are only alternatives, and the words of
+    /// the flags. A term with `OR` outside of parentheses requires none
+    fn required_words(&self) -> Vec<&str> {
+        let mut groups = Vec::new();
+        let (mut start, mut depth, mut quoted) = (0, 0, false);
+        for (i, c) in self.term.char_indices() {
+            match c {
+                '"' => quoted = !quoted,
+                '(' if !quoted => depth += 1,
+                ')' if !quoted => depth = usize::saturating_sub(depth, 1),
+                c if c.is_whitespace() && !quoted && depth == 0 => {
+                    groups.push(&self.term[start..i]);
+                    start = i + c.len_utf8();
+                }
+                _ => {}
+            }
+        }
+        groups.push(&self.term[start..]);
+        let mut words = Vec::new();
+        if !groups.contains(&"OR") {
+            for group in groups {
+                let group_words = group
+                    .split_whitespace()
+                    .map(|word| word.trim_matches(|c| c == '(' || c == ')'))
+                    .collect::<Vec<&str>>();
+                if !group_words.contains(&"OR") {
+                    words.extend(group_words);
+                }
+            }
+        }
+        if let [word] = self.any.as_slice() {
+            words.push(word);
+        }
+        words.extend(self.all.iter().map(String::as_str));
+        words
+    }
+
+    /// Checks the query and returns it. A kind the term already excludes
+    /// isn't added again, and one the term requires is an error since no
+    /// tweet could match, like a second language. The alternatives of an
+    /// `OR` may still match other tweets, so they aren't checked
+    fn build(&self) -> Result<String, String> {
+        let required = self.required_words();
+        if let Some(lang) = &self.lang {
+            if required.iter().any(|word| word.starts_with("lang:")) {
+                return Err(format!(
+                    "🙅 The term already asks for a language, which `--lang {}` can't be added to",
+                    lang
+                ));
+            }
+        }
+        for (kind, flag) in &self.excluded {
+            let operator = format!("is:{}", kind);
+            if required.contains(&operator.as_str()) {
+                return Err(format!(
+                    "🙅 The term asks for `{}`, which `{}` leaves out",
+                    operator, flag
+                ));
+            }
+        }
+        let query = self.to_string();
+        let length = query.chars().count();
+        if length > MAX_QUERY_LENGTH {
+            return Err(format!(
+                "🙅 The search query is {} characters long but Twitter accepts up to {}: {}",
+                length, MAX_QUERY_LENGTH, query
+            ));
+        }
+        Ok(query)
+    }
+}
+
+impl fmt::Display for QueryBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let base = self.base();
+        write!(f, "{}", base)?;
+        for (kind, _) in &self.excluded {
+            let operator = format!("-is:{}", kind);
+            if !base
+                .split_whitespace()
+                .any(|word| word.trim_matches(|c| c == '(' || c == ')') == operator)
+            {
+                write!(f, " {}", operator)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn validate_term_search(name: &str) -> Result<(), String> {
+    if name.is_empty() {
+        Err(String::from("The term cannot be empty"))
+    } else if name.trim().len() != name.len() {
+        Err(String::from(
+            "search term name cannot have leading and trailing space",
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+fn validate_output_path(output: &str) -> Result<(), String> {
+    if output.trim().len() != output.len() {
+        Err(String::from(
+            "output cannot have leading and trailing space",
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Parses the three descending thresholds of `--mood-thresholds`, from 0.5
+/// to 1
+fn parse_mood_thresholds(thresholds: &str) -> Result<[f64; 3], String> {
+    let error = || {
+        String::from(
+            "the thresholds must be three descending numbers from 0.5 to 1, like 0.95,0.8,0.6",
+        )
+    };
+    let values = thresholds
+        .split(',')
+        .map(|value| value.trim().parse::<f64>().map_err(|_| error()))
+        .collect::<Result<Vec<f64>, String>>()?;
+    match values.as_slice() {
+        &[strong, medium, mild]
+            if strong >= medium && medium >= mild && mild >= 0.5 && strong <= 1.0 =>
+        {
+            Ok([strong, medium, mild])
+        }
+        _ => Err(error()),
+    }
+}
+
+/// Coarse mood of a happiness score. Negative tweets get the mirrored moods,
+/// measured on their probability of being negative
+fn mood(happiness: f64, [strong, medium, mild]: [f64; 3]) -> &'static str {
+    let (score, moods) = if happiness >= 0.5 {
+        (happiness, ["ecstatic", "happy", "content"])
+    } else {
+        (1.0 - happiness, ["miserable", "sad", "glum"])
+    };
+    if score >= strong {
+        moods[0]
+    } else if score >= medium {
+        moods[1]
+    } else if score >= mild {
+        moods[2]
+    } else {
+        "neutral"
+    }
+}
+
+fn validate_emoji_boost(delta: &str) -> Result<(), String> {
+    match delta.parse::<f64>() {
+        Ok(delta) if (0.0..=1.0).contains(&delta) => Ok(()),
+        _ => Err(String::from("the boost must be a number from 0 to 1")),
+    }
+}
+
+fn validate_bytes(bytes: &str) -> Result<(), String> {
+    match bytes.parse::<usize>() {
+        Ok(bytes) if bytes > 0 => Ok(()),
+        _ => Err(String::from("it must be a positive number of bytes")),
+    }
+}
+
+fn validate_threshold(threshold: &str) -> Result<(), String> {
+    match threshold.parse::<f64>() {
+        Ok(threshold) if (0.0..=1.0).contains(&threshold) => Ok(()),
+        _ => Err(String::from("the threshold must be a number from 0 to 1")),
+    }
+}
+
+/// Parses a `start-end` range of hours of `--hours`, from 0 to 24
+fn parse_hours(hours: &str) -> Result<(u32, u32), String> {
+    let error = || {
+        format!(
+            "`{}` must be two different hours from 0 to 24 as `start-end`, e.g. `9-17` or `22-2`",
+            hours
+        )
+    };
+    let (start, end) = hours.split_once('-').ok_or_else(error)?;
+    let hour = |hour: &str| hour.trim().parse::<u32>().ok().filter(|&hour| hour <= 24);
+    match (hour(start), hour(end)) {
+        (Some(start), Some(end)) if start % 24 != end % 24 => Ok((start % 24, end % 24)),
+        (Some(0), Some(24)) => Ok((0, 24)),
+        _ => Err(error()),
+    }
+}
+
+/// Parses a `positive:negative` ratio of `--balance`
+fn parse_balance(ratio: &str) -> Result<(f64, f64), String> {
+    let parse = |part: &str| part.trim().parse::<f64>().ok().filter(|n| *n > 0.0);
+    match ratio.split_once(':') {
+        Some((positive, negative)) => match (parse(positive), parse(negative)) {
+            (Some(positive), Some(negative)) => Ok((positive, negative)),
+            _ => Err(String::from(
+                "both sides of the ratio must be numbers over 0",
+            )),
+        },
+        None => Err(String::from(
+            "the ratio must be `positive:negative`, e.g. `1:1`",
+        )),
+    }
+}
+
+/// Parses a window of `--dedup-window`, `--half-life` or `--retry-budget`
+/// made of a positive number and a unit: `m` for minutes, `h` for hours, `d`
+/// for days or `w` for weeks
+fn parse_window(window: &str) -> Result<Duration, String> {
+    let error = || {
+        format!(
+            "`{}` must be a positive number followed by m, h, d or w, like 7d",
+            window
+        )
+    };
+    let unit = match window.chars().last() {
+        Some('m') => 60,
+        Some('h') => 60 * 60,
+        Some('d') => 24 * 60 * 60,
+        Some('w') => 7 * 24 * 60 * 60,
+        _ => return Err(error()),
+    };
+    let amount = window[..window.len() - 1]
+        .parse::<u64>()
+        .ok()
+        .filter(|&amount| amount > 0)
+        .ok_or_else(error)?;
+    Ok(Duration::from_secs(amount * unit))
+}
+
+/// Timeline `--timeline` reads instead of the recent search
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq)]
+enum Timeline {
+    /// Tweets mentioning the user
+    Mentions,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq)]
+enum SortOrder {
+    Happiest,
+    Trending,
+}
+
+impl SortOrder {
+    /// Sorts the tweets from the highest score down. The trending score is
+    ///
+    /// `happiness * 0.5 ^ (age / half_life)`
+    ///
+    /// so a tweet just posted keeps its happiness and one `half_life` old
+    /// counts half. Ties keep the newest first, then go by url, so the same
+    /// tweets are always written in the same order
+    fn sort(self, tweets: &mut [HappyTweet], now: DateTime<Utc>, half_life: Duration) {
+        let score = |tweet: &HappyTweet| {
+            let happiness = tweet.sentiment.as_ref().map(happiness).unwrap_or_default();
+            match self {
+                SortOrder::Happiest => happiness,
+                SortOrder::Trending => {
+                    let age = posted_at(tweet.id())
+                        .map(|posted_at| (now - posted_at).num_milliseconds().max(0))
+                        .unwrap_or_default() as f64;
+                    happiness * 0.5_f64.powf(age / half_life.as_millis() as f64)
+                }
+            }
+        };
+        tweets.sort_by(|a, b| {
+            score(b)
+                .total_cmp(&score(a))
+                .then_with(|| b.chronological(a))
+                .then_with(|| a.tweet.url.cmp(&b.tweet.url))
+        });
+    }
+}
+
+/// Time a tweet was posted, which Twitter encodes in its id
+fn posted_at(id: &str) -> Option<DateTime<Utc>> {
+    let id = id.parse::<i64>().ok()?;
+    Utc.timestamp_millis_opt((id >> 22) + TWITTER_EPOCH_MILLIS)
+        .single()
+}
+
+fn validate_concurrency(concurrency: &str) -> Result<(), String> {
+    match concurrency.parse::<usize>() {
+        Ok(concurrency) if (1..=MAX_CONCURRENCY).contains(&concurrency) => Ok(()),
+        _ => Err(format!("it must be a number from 1 to {}", MAX_CONCURRENCY)),
+    }
+}
+
+fn validate_near(point: &str) -> Result<(), String> {
+    let error = || {
+        format!(
+            "`{}` must be `longitude,latitude,radius` with the radius in `km` or `mi` up to 25 miles, e.g. `-0.37,39.47,10km`",
+            point
+        )
+    };
+    let (longitude, latitude, radius) = match point.split(',').collect::<Vec<&str>>()[..] {
+        [longitude, latitude, radius] => (longitude, latitude, radius),
+        _ => return Err(error()),
+    };
+    let coordinate = |coordinate: &str, limit: f64| {
+        coordinate
+            .parse::<f64>()
+            .ok()
+            .filter(|coordinate| coordinate.abs() <= limit)
+    };
+    let miles = match (radius.strip_suffix("km"), radius.strip_suffix("mi")) {
+        (Some(km), _) => km.parse::<f64>().ok().map(|km| km / KM_PER_MILE),
+        (_, Some(mi)) => mi.parse::<f64>().ok(),
+        _ => None,
+    };
+    match (
+        coordinate(longitude, 180.0),
+        coordinate(latitude, 90.0),
+        miles,
+    ) {
+        (Some(_), Some(_), Some(miles)) if miles > 0.0 && miles <= MAX_RADIUS_MILES => Ok(()),
+        _ => Err(error()),
+    }
+}
+
+fn validate_lang(lang: &str) -> Result<(), String> {
+    let (language, region) = lang.split_once('-').unwrap_or((lang, ""));
+    if (2..=3).contains(&language.len())
+        && language.chars().all(|c| c.is_ascii_lowercase())
+        && region.chars().all(|c| c.is_ascii_alphanumeric())
+    {
+        Ok(())
+    } else {
+        Err(format!(
+            "`{}` is not a language tag like Twitter detects, e.g. \"es\" or \"zh-CN\"",
+            lang
+        ))
+    }
+}
+
+fn validate_timezone(timezone: &str) -> Result<(), String> {
+    timezone.parse::<Tz>().map(|_| ()).map_err(|_| {
+        format!(
+            "`{}` is not an IANA timezone name, e.g. \"Europe/Madrid\"",
+            timezone
+        )
+    })
+}
+
+fn validate_date_format(date_format: &str) -> Result<(), String> {
+    if StrftimeItems::new(date_format).any(|item| item == Item::Error) {
+        Err(format!("`{}` is not a valid date format", date_format))
+    } else {
+        Ok(())
+    }
+}
+
+/// Reads the tweets of a previous run. The file must be a JSON array of
+/// tweets or an envelope, any other format fails suggesting `--overwrite` so
+/// the content is never lost silently
+fn read_output_file(path: &std::path::Path) -> Result<OutputFile, String> {
+    let mismatch = |reason: String| {
+        format!(
+            "🙅 Can't append the results to `{}`: {}. Use another output or `--overwrite` to replace it",
+            path.display(),
+            reason
+        )
+    };
+    if let Some(format) = OutputFormat::from_path(path) {
+        if format != OutputFormat::Json {
+            return Err(mismatch(format!(
+                "it looks like a {:?} file but the output is JSON",
+                format
+            )));
+        }
+    }
+    let contents = read_output(path).map_err(|e| mismatch(e.to_string()))?;
+    if contents.trim().is_empty() {
+        return Ok(OutputFile::Tweets(Vec::new()));
+    }
+    serde_json::from_str(&contents).map_err(|_| {
+        if serde_json::from_str::<Value>(&contents).is_err() {
+            mismatch(String::from(CORRUPTED_REASON))
+        } else {
+            mismatch(String::from(
+                "it is neither a JSON array of tweets nor an envelope",
+            ))
+        }
+    })
+}
+
+/// Format of the tweets an existing output holds, read from its content: JSON
+/// (an array or an envelope) or NDJSON. `None` when it holds no tweets, like
+/// Markdown or `--fields` outputs
+fn existing_tweets_format(path: &std::path::Path) -> Option<OutputFormat> {
+    let contents = read_output(path).ok()?;
+    if let Ok(file) = serde_json::from_str::<OutputFile>(&contents) {
+        return (!file.into_tweets().is_empty()).then_some(OutputFormat::Json);
+    }
+    let mut lines = contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .peekable();
+    lines.peek()?;
+    lines
+        .all(|line| {
+            serde_json::from_str::<HappyTweet>(line).is_ok()
+                || serde_json::from_str::<Cursor>(line).is_ok()
+        })
+        .then_some(OutputFormat::Ndjson)
+}
+
+/// Refuses to write `format` to an output holding tweets in another format,
+/// which would lose them: Markdown and `--fields` replace the file, and JSON
+/// and NDJSON can't be appended to each other. `--overwrite` replaces it
+fn check_existing_format(
+    path: &std::path::Path,
+    format: OutputFormat,
+    options: &WriteOptions,
+) -> Result<(), String> {
+    if options.overwrite || path == std::path::Path::new(STDOUT_PATH) || !path.is_file() {
+        return Ok(());
+    }
+    let existing = match existing_tweets_format(path) {
+        Some(existing) => existing,
+        None => return Ok(()),
+    };
+    let writing = if !options.fields.is_empty() {
+        String::from("only some fields")
+    } else if format == existing {
+        return Ok(());
+    } else {
+        format!("{:?}", format)
+    };
+    Err(format!(
+        "🙅 `{}` holds tweets in {:?}, writing {} to it would lose them. Use `--format {}` to append to it, another output, or `--overwrite` to replace it",
+        path.display(),
+        existing,
+        writing,
+        existing.extension()
+    ))
+}
+
+/// Tells whether `path` is not valid JSON (or NDJSON), which happens when a
+/// run is interrupted while writing it
+fn is_corrupted(path: &std::path::Path, format: OutputFormat) -> bool {
+    let contents = match read_output(path) {
+        Ok(contents) => contents,
+        Err(_) => return false,
+    };
+    match format {
+        OutputFormat::Json => {
+            !contents.trim().is_empty() && serde_json::from_str::<Value>(&contents).is_err()
+        }
+        OutputFormat::Ndjson => contents
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .any(|line| serde_json::from_str::<Value>(line).is_err()),
+        OutputFormat::Markdown | OutputFormat::Table => false,
+    }
+}
+
+/// Reads an output, decompressing it when its extension is the one of a
+/// compression. The members appended to a compressed NDJSON are all read, and
+/// outputs written with `--output-encoding` are read back as text
+fn read_output(path: &std::path::Path) -> std::io::Result<String> {
+    let bytes = read_output_bytes(path)?;
+    let bytes = bytes.strip_prefix(UTF8_BOM).unwrap_or(&bytes);
+    Ok(match std::str::from_utf8(bytes) {
+        Ok(contents) => contents.to_string(),
+        Err(_) => bytes.iter().map(|&byte| char::from(byte)).collect(),
+    })
+}
+
+/// Decompressed bytes of an output
+fn read_output_bytes(path: &std::path::Path) -> std::io::Result<Vec<u8>> {
+    let file = File::open(path)?;
+    let mut bytes = Vec::new();
+    match Compression::from_path(path) {
+        Some(Compression::Gzip) => MultiGzDecoder::new(file).read_to_end(&mut bytes)?,
+        Some(Compression::Zstd) => zstd::Decoder::new(file)?.read_to_end(&mut bytes)?,
+        None => BufReader::new(file).read_to_end(&mut bytes)?,
+    };
+    Ok(bytes)
+}
+
+enum Encoder {
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+    Zstd(zstd::Encoder<'static, BufWriter<File>>),
+}
+
+impl Write for Encoder {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Encoder::Plain(writer) => writer.write(buf),
+            Encoder::Gzip(encoder) => encoder.write(buf),
+            Encoder::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Encoder::Plain(writer) => writer.flush(),
+            Encoder::Gzip(encoder) => encoder.flush(),
+            Encoder::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// Writer of an output in the `--output-encoding`, compressed when its
+/// extension is the one of a compression. Replaced and compressed outputs are
+/// written to a temporary file, starting as a copy of the output when
+/// appending, which is renamed on `finish` so an interrupted run never leaves
+/// a truncated output. Plain files appended to, like NDJSON, are written in
+/// place, and so are special files like `/dev/stdout`
+struct OutputWriter {
+    path: std::path::PathBuf,
+    encoder: Encoder,
+    encoding: OutputEncoding,
+    /// Start of a character split across writes, transcoded with the next
+    pending: Vec<u8>,
+    /// Characters replaced because the encoding can't represent them
+    replaced: usize,
+    rename: Option<std::path::PathBuf>,
+}
+
+impl OutputWriter {
+    fn create(
+        path: &std::path::Path,
+        append: bool,
+        encoding: OutputEncoding,
+    ) -> std::io::Result<OutputWriter> {
+        let is_empty = !append || path.metadata().map_or(true, |metadata| metadata.len() == 0);
+        let compression = Compression::from_path(path);
+        let tmp = std::path::PathBuf::from(format!("{}.tmp", path.display()));
+        let is_regular = path
+            .symlink_metadata()
+            .map_or(true, |metadata| metadata.is_file());
+        let atomic = is_regular && (compression.is_some() || !append);
+        if atomic && append && path.exists() {
+            std::fs::copy(path, &tmp)?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(if atomic { &tmp } else { path })?;
+        let writer = BufWriter::new(file);
+        let mut encoder = match compression {
+            None => Encoder::Plain(writer),
+            Some(Compression::Gzip) => {
+                Encoder::Gzip(GzEncoder::new(writer, flate2::Compression::default()))
+            }
+            Some(Compression::Zstd) => Encoder::Zstd(zstd::Encoder::new(writer, 0)?),
+        };
+        if encoding == OutputEncoding::Utf8Bom && is_empty {
+            encoder.write_all(UTF8_BOM)?;
+        }
+        Ok(OutputWriter {
+            path: path.to_path_buf(),
+            encoder,
+            encoding,
+            pending: Vec::new(),
+            replaced: 0,
+            rename: atomic.then_some(tmp),
+        })
+    }
+
+    /// Ends the compressed stream and moves the temporary file in place
+    fn finish(self) -> std::io::Result<()> {
+        if self.replaced > 0 {
+            println!(
+                "⚠️  {} characters can't be written in {:?} to `{}`, they were replaced by `?`",
+                self.replaced,
+                self.encoding,
+                self.path.display()
+            );
+        }
+        let mut writer = match self.encoder {
+            Encoder::Plain(writer) => writer,
+            Encoder::Gzip(encoder) => encoder.finish()?,
+            Encoder::Zstd(encoder) => encoder.finish()?,
+        };
+        writer.flush()?;
+        drop(writer);
+        if let Some(tmp) = self.rename {
+            std::fs::rename(tmp, self.path)?;
+        }
+        Ok(())
+    }
+}
+
+impl Write for OutputWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.encoding != OutputEncoding::Latin1 {
+            return self.encoder.write(buf);
+        }
+        self.pending.extend_from_slice(buf);
+        let valid = match std::str::from_utf8(&self.pending) {
+            Ok(text) => text.len(),
+            Err(error) => error.valid_up_to(),
+        };
+        let text = std::str::from_utf8(&self.pending[..valid]).expect("checked above");
+        let bytes = text
+            .chars()
+            .map(|c| {
+                u8::try_from(c).unwrap_or_else(|_| {
+                    self.replaced += 1;
+                    b'?'
+                })
+            })
+            .collect::<Vec<u8>>();
+        self.encoder.write_all(&bytes)?;
+        self.pending.drain(..valid);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.encoder.flush()
+    }
+}
+
+/// Moves a corrupted output aside, so it can be inspected while the run
+/// starts the file afresh
+fn back_up_corrupted(path: &std::path::Path) -> Result<(), String> {
+    let backup = std::path::PathBuf::from(format!(
+        "{}.corrupted-{}",
+        path.display(),
+        Utc::now().format("%Y%m%dT%H%M%S")
+    ));
+    std::fs::rename(path, &backup).map_err(|e| e.to_string())?;
+    println!(
+        "🩹 `{}` was corrupted, it was moved to `{}` and a new file is started",
+        path.display(),
+        backup.display()
+    );
+    Ok(())
+}
+
+/// Keeps the results of the run when writing `path` failed with an I/O
+/// `error`, so a long crawl isn't lost to a full disk: `contents` are saved to
+/// a file of the temporary directory with the `extension` of their format, or
+/// printed to the standard output with `to_stdout`. Returns the error to stop
+/// the run with, telling where the results went. Other errors, like an output
+/// that can't be appended to, are returned as they are, as the run can be
+/// repeated once they are fixed
+fn rescue_output(
+    path: &std::path::Path,
+    error: Box<dyn std::error::Error>,
+    contents: impl FnOnce() -> serde_json::Result<Vec<u8>>,
+    extension: &str,
+    to_stdout: bool,
+) -> Box<dyn std::error::Error> {
+    if !is_io_error(error.as_ref()) {
+        return error;
+    }
+    let fallback = if to_stdout && path != std::path::Path::new(STDOUT_PATH) {
+        std::path::PathBuf::from(STDOUT_PATH)
+    } else {
+        env::temp_dir().join(format!(
+            "{}-{}.{}",
+            DEFAULT_OUTPUT_NAME,
+            Utc::now().format("%Y%m%dT%H%M%S"),
+            extension
+        ))
+    };
+    let rescued = contents()
+        .map_err(Box::<dyn std::error::Error>::from)
+        .and_then(|contents| {
+            let mut file = File::create(&fallback)?;
+            file.write_all(&contents)?;
+            file.flush()?;
+            Ok(())
+        });
+    match rescued {
+        Ok(()) => format!(
+            "🙅 Couldn't write `{}`: {}. The results were written to `{}` instead",
+            path.display(),
+            error,
+            fallback.display()
+        ),
+        Err(rescue_error) => format!(
+            "🙅 Couldn't write `{}`: {}. Writing the results to `{}` failed too: {}",
+            path.display(),
+            error,
+            fallback.display(),
+            rescue_error
+        ),
+    }
+    .into()
+}
+
+/// Whether writing failed on the file system, like a full disk, rather than
+/// on a check of the output
+fn is_io_error(error: &(dyn std::error::Error + 'static)) -> bool {
+    error.downcast_ref::<std::io::Error>().is_some()
+        || error
+            .downcast_ref::<serde_json::Error>()
+            .is_some_and(serde_json::Error::is_io)
+}
+
+/// Serializes the `items` as NDJSON, one per line
+fn ndjson_lines<T: Serialize>(items: &[T]) -> serde_json::Result<Vec<u8>> {
+    let mut lines = Vec::new();
+    for item in items {
+        serde_json::to_writer(&mut lines, item)?;
+        lines.push(b'\n');
+    }
+    Ok(lines)
+}
+
+/// MinHash signature of the word trigrams of `text`. The share of equal
+/// positions between two signatures estimates how similar both texts are.
+/// Texts without words get an empty signature that never matches
+fn minhash(text: &str) -> Vec<u64> {
+    let words = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<String>>();
+    if words.is_empty() {
+        return Vec::new();
+    }
+    let shingles = words
+        .windows(3.min(words.len()))
+        .collect::<Vec<&[String]>>();
+    (0..MINHASH_SIZE)
+        .map(|seed| {
+            shingles
+                .iter()
+                .map(|shingle| {
+                    let mut hasher = DefaultHasher::new();
+                    (seed, shingle).hash(&mut hasher);
+                    hasher.finish()
+                })
+                .min()
+                .unwrap_or(u64::MAX)
+        })
+        .collect()
+}
+
+fn similarity(a: &[u64], b: &[u64]) -> f64 {
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    a.iter().zip(b).filter(|(a, b)| a == b).count() as f64 / MINHASH_SIZE as f64
+}
+
+/// Zero-width characters stripped by `--strip-zero-width`. The zero-width
+/// joiner is left out, as emoji like 👩‍💻 are built with it
+const ZERO_WIDTH_CHARS: [char; 4] = ['\u{200B}', '\u{200C}', '\u{2060}', '\u{FEFF}'];
+
+/// Cleans up the content of the tweets for `--normalize-content`
+#[derive(Clone, Copy)]
+struct ContentNormalizer {
+    strip_zero_width: bool,
+}
+
+impl ContentNormalizer {
+    fn new(args: &Arguments) -> Option<ContentNormalizer> {
+        args.normalize_content.then_some(ContentNormalizer {
+            strip_zero_width: args.strip_zero_width,
+        })
+    }
+
+    /// `text` with its whitespace collapsed into single spaces and trimmed.
+    /// Zero-width characters are stripped first, so the spaces around them
+    /// are collapsed too
+    fn normalize(&self, text: &str) -> String {
+        let stripped;
+        let text = if self.strip_zero_width {
+            stripped = text.replace(ZERO_WIDTH_CHARS, "");
+            &stripped
+        } else {
+            text
+        };
+        text.split_whitespace().collect::<Vec<&str>>().join(" ")
+    }
+}
+
+/// Number of different words of `text`, case-insensitive. Mentions and urls
+/// are not words, and neither are emoji and punctuation, so a tweet of only
+/// emoji has none
+fn unique_words(text: &str) -> usize {
+    text.split_whitespace()
+        .filter(|token| !token.starts_with('@') && !token.contains("://"))
+        .flat_map(|token| token.split(|c: char| !c.is_alphanumeric()))
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<HashSet<String>>()
+        .len()
+}
+
+/// Replaces the retweets by the tweet they retweet, found in the included
+/// tweets, keeping each original once so it isn't counted several times.
+/// Retweets of tweets missing from the response are kept as they are.
+/// Returns the number of retweets collapsed
+fn collapse_retweets(data: &mut TwitterApiResponse) -> usize {
+    let mut collapsed = 0;
+    let mut seen = HashSet::new();
+    for tweet in std::mem::take(&mut data.data) {
+        let original = tweet
+            .referenced_tweets
+            .iter()
+            .find(|reference| reference.reference_type == "retweeted")
+            .and_then(|reference| {
+                data.includes
+                    .tweets
+                    .iter()
+                    .find(|included| included.id == reference.id)
+            });
+        let tweet = match original {
+            Some(original) => {
+                collapsed += 1;
+                original.clone()
+            }
+            None => tweet,
+        };
+        if seen.insert(tweet.id.clone()) {
+            data.data.push(tweet);
+        }
+    }
+    collapsed
+}
+
+/// Id of the author `tweet` is written with: the one of its original when
+/// `collapse` replaces a retweet by it, as collapse_retweets does
+fn written_author<'a>(
+    tweet: &'a TwitterApiResponseData,
+    includes: &'a TwitterApiResponseIncludes,
+    collapse: bool,
+) -> &'a str {
+    tweet
+        .referenced_tweets
+        .iter()
+        .find(|reference| collapse && reference.reference_type == "retweeted")
+        .and_then(|reference| {
+            includes
+                .tweets
+                .iter()
+                .find(|included| included.id == reference.id)
+        })
+        .unwrap_or(tweet)
+        .author_id
+        .as_str()
+}
+
+/// Drops the tweets whose author isn't kept by `keeps`, or is missing from the
+/// included users, and returns how many were dropped
+fn retain_authors(
+    data: &mut TwitterApiResponse,
+    keeps: impl Fn(&TwitterApiResponseUser) -> bool,
+) -> usize {
+    let kept = data
+        .includes
+        .users
+        .iter()
+        .filter(|user| keeps(user))
+        .map(|user| user.id.as_str())
+        .collect::<HashSet<&str>>();
+    let total = data.data.len();
+    data.data
+        .retain(|tweet| kept.contains(tweet.author_id.as_str()));
+    total - data.data.len()
+}
+
+/// Drops the tweets whose `text` is similar to an earlier one, as told by
+/// `chronological`. Every tweet is compared with all the tweets kept so far,
+/// which is quadratic on the number of tweets
+fn dedup_fuzzy<T>(
+    tweets: Vec<T>,
+    threshold: f64,
+    text: impl Fn(&T) -> &str,
+    chronological: impl Fn(&T, &T) -> Ordering,
+) -> Vec<T> {
+    let mut order = (0..tweets.len()).collect::<Vec<usize>>();
+    order.sort_by(|&a, &b| chronological(&tweets[a], &tweets[b]));
+
+    let mut kept: Vec<(usize, Vec<u64>)> = Vec::new();
+    for i in order {
+        let signature = minhash(text(&tweets[i]));
+        if !kept
+            .iter()
+            .any(|(_, kept)| similarity(kept, &signature) >= threshold)
+        {
+            kept.push((i, signature));
+        }
+    }
+
+    let mut selected = vec![false; tweets.len()];
+    for (i, _) in kept {
+        selected[i] = true;
+    }
+    tweets
+        .into_iter()
+        .zip(selected)
+        .filter_map(|(tweet, selected)| selected.then_some(tweet))
+        .collect()
+}
+
+/// Client-side filter of the tweets found, applied before they are classified
+/// so the dropped ones don't cost any model time
+trait TweetFilter {
+    fn keep(&self, tweet: &TwitterApiResponseData) -> bool;
+
+    /// Message telling how many tweets the filter dropped
+    fn report(&self, dropped: &[TwitterApiResponseData]) -> String;
+}
+
+/// Splits the tweets into the ones all the `filters` keep and the ones each
+/// filter dropped. Each tweet is counted as dropped by the first filter it
+/// fails
+fn apply_filters(
+    filters: &[Box<dyn TweetFilter>],
+    tweets: Vec<TwitterApiResponseData>,
+) -> (
+    Vec<TwitterApiResponseData>,
+    Vec<Vec<TwitterApiResponseData>>,
+) {
+    let mut kept = Vec::new();
+    let mut dropped: Vec<Vec<TwitterApiResponseData>> =
+        filters.iter().map(|_| Vec::new()).collect();
+    for tweet in tweets {
+        match filters.iter().position(|filter| !filter.keep(&tweet)) {
+            Some(i) => dropped[i].push(tweet),
+            None => kept.push(tweet),
+        }
+    }
+    (kept, dropped)
+}
+
+/// Filters enabled by the arguments, in the order they are applied
+fn tweet_filters(args: &Arguments) -> Result<Vec<Box<dyn TweetFilter>>, String> {
+    let mut filters: Vec<Box<dyn TweetFilter>> = Vec::new();
+    let keywords = KeywordFilter::new(&args.must_contain, &args.any_of, args.match_regex)?;
+    if !keywords.is_empty() {
+        filters.push(Box::new(keywords));
+    }
+    let hashtags = HashtagFilter::new(&args.hashtag, &args.exclude_hashtag);
+    if !hashtags.is_empty() {
+        filters.push(Box::new(hashtags));
+    }
+    if !args.context.is_empty() {
+        filters.push(Box::new(ContextFilter::new(&args.context)));
+    }
+    if args.min_unique_words > 0 {
+        filters.push(Box::new(UniqueWordsFilter {
+            min: args.min_unique_words,
+        }));
+    }
+    if args.no_sensitive {
+        filters.push(Box::new(SensitiveFilter));
+    }
+    if let Some(hours) = &args.hours {
+        let (start, end) = parse_hours(hours)?;
+        filters.push(Box::new(HoursFilter {
+            start,
+            end,
+            timezone: args.timezone,
+        }));
+    }
+    Ok(filters)
+}
+
+/// Keeps the tweets Twitter annotated with any of the names, compared
+/// case-insensitively
+struct ContextFilter {
+    names: Vec<String>,
+}
+
+impl ContextFilter {
+    fn new(names: &[String]) -> ContextFilter {
+        ContextFilter {
+            names: names.iter().map(|name| name.to_lowercase()).collect(),
+        }
+    }
+}
+
+impl TweetFilter for ContextFilter {
+    fn keep(&self, tweet: &TwitterApiResponseData) -> bool {
+        tweet
+            .context()
+            .iter()
+            .any(|context| self.names.contains(&context.to_lowercase()))
+    }
+
+    fn report(&self, dropped: &[TwitterApiResponseData]) -> String {
+        format!("🏷️  Dropped {} tweets out of the context", dropped.len())
+    }
+}
+
+/// Usernames of `--exclude-authors` or `--only-authors`, lowercased and
+/// without `@`
+struct AuthorList {
+    usernames: HashSet<String>,
+    /// Whether the tweets of the list are the only ones kept
+    only: bool,
+}
+
+impl AuthorList {
+    fn from_args(args: &Arguments) -> Result<Option<AuthorList>, String> {
+        let (list, only) = match (&args.exclude_authors, &args.only_authors) {
+            (Some(list), _) => (list, false),
+            (None, Some(list)) => (list, true),
+            (None, None) => return Ok(None),
+        };
+        let path = std::path::Path::new(list);
+        let usernames = if path.is_file() {
+            std::fs::read_to_string(path)
+                .map_err(|e| format!("🙅 Can't read the authors of `{}`: {}", path.display(), e))?
+                .lines()
+                .map(str::to_string)
+                .collect::<Vec<String>>()
+        } else {
+            list.split(',').map(str::to_string).collect()
+        };
+        let usernames = usernames
+            .iter()
+            .map(|username| username.trim().trim_start_matches('@').to_lowercase())
+            .filter(|username| !username.is_empty())
+            .collect::<HashSet<String>>();
+        if usernames.is_empty() {
+            return Err(format!("🙅 There are no usernames in `{}`", list));
+        }
+        Ok(Some(AuthorList { usernames, only }))
+    }
+
+    fn keeps(&self, username: &str) -> bool {
+        self.usernames.contains(&username.to_lowercase()) == self.only
+    }
+}
+
+/// Part of `profile_image_url` of the authors who kept the default picture, the
+/// egg and later the silhouette, like
+/// `https://abs.twimg.com/sticky/default_profile_images/default_profile_normal.png`
+static DEFAULT_PROFILE_IMAGE_PATH: &str = "/default_profile_images/";
+
+/// Parts of the profile the authors must have filled in, from
+/// `--require-avatar` and `--require-bio`
+struct ProfileFilter {
+    avatar: bool,
+    bio: bool,
+}
+
+impl ProfileFilter {
+    fn from_args(args: &Arguments) -> Option<ProfileFilter> {
+        (args.require_avatar || args.require_bio).then_some(ProfileFilter {
+            avatar: args.require_avatar,
+            bio: args.require_bio,
+        })
+    }
+
+    fn keeps(&self, user: &TwitterApiResponseUser) -> bool {
+        let has_avatar = !user.profile_image_url.contains(DEFAULT_PROFILE_IMAGE_PATH);
+        let has_bio = user
+            .description
+            .as_deref()
+            .is_some_and(|bio| !bio.trim().is_empty());
+        (has_avatar || !self.avatar) && (has_bio || !self.bio)
+    }
+
+    /// What the authors dropped lack, for the report
+    fn missing(&self) -> &'static str {
+        match (self.avatar, self.bio) {
+            (true, true) => "without a profile picture or a bio",
+            (true, false) => "without a profile picture",
+            _ => "without a bio",
+        }
+    }
+}
+
+/// Keeps the tweets posted from the hour `start` to the hour `end` of the day,
+/// past midnight when `end` comes first
+struct HoursFilter {
+    start: u32,
+    end: u32,
+    timezone: Option<Tz>,
+}
+
+impl TweetFilter for HoursFilter {
+    fn keep(&self, tweet: &TwitterApiResponseData) -> bool {
+        let date = match DateTime::parse_from_rfc3339(&tweet.created_at) {
+            Ok(date) => date,
+            // The invalid date fails when the tweet is written
+            Err(_) => return true,
+        };
+        let hour = match self.timezone {
+            Some(timezone) => date.with_timezone(&timezone).hour(),
+            None => date.with_timezone(&Utc).hour(),
+        };
+        if self.start < self.end {
+            (self.start..self.end).contains(&hour)
+        } else {
+            hour >= self.start || hour < self.end
+        }
+    }
+
+    fn report(&self, dropped: &[TwitterApiResponseData]) -> String {
+        format!(
+            "🕘 Dropped {} tweets posted out of the hours {}-{}",
+            dropped.len(),
+            self.start,
+            self.end
+        )
+    }
+}
+
+/// Drops the tweets flagged as possibly sensitive. Tweets without the flag
+/// are kept
+struct SensitiveFilter;
+
+impl TweetFilter for SensitiveFilter {
+    fn keep(&self, tweet: &TwitterApiResponseData) -> bool {
+        tweet.possibly_sensitive != Some(true)
+    }
+
+    fn report(&self, dropped: &[TwitterApiResponseData]) -> String {
+        format!(
+            "🔞 Dropped {} tweets flagged as possibly sensitive",
+            dropped.len()
+        )
+    }
+}
+
+/// Drops low-effort tweets with fewer different words than `min`
+struct UniqueWordsFilter {
+    min: usize,
+}
+
+impl TweetFilter for UniqueWordsFilter {
+    fn keep(&self, tweet: &TwitterApiResponseData) -> bool {
+        unique_words(&tweet.text) >= self.min
+    }
+
+    fn report(&self, dropped: &[TwitterApiResponseData]) -> String {
+        let emoji_only = dropped
+            .iter()
+            .filter(|tweet| unique_words(&tweet.text) == 0 && !tweet.text.trim().is_empty())
+            .count();
+        format!(
+            "🧹 Dropped {} tweets with fewer than {} different words, {} of them without any word",
+            dropped.len(),
+            self.min,
+            emoji_only
+        )
+    }
+}
+
+/// Client-side gate on the hashtags Twitter parsed from the tweets, compared
+/// case-insensitively
+struct HashtagFilter {
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+impl HashtagFilter {
+    fn new(include: &[String], exclude: &[String]) -> HashtagFilter {
+        let normalize = |tags: &[String]| {
+            tags.iter()
+                .map(|tag| tag.trim_start_matches('#').to_lowercase())
+                .collect()
+        };
+        HashtagFilter {
+            include: normalize(include),
+            exclude: normalize(exclude),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+}
+
+impl TweetFilter for HashtagFilter {
+    fn keep(&self, tweet: &TwitterApiResponseData) -> bool {
+        let tags = tweet
+            .hashtags()
+            .map(str::to_lowercase)
+            .collect::<Vec<String>>();
+        (self.include.is_empty() || self.include.iter().any(|tag| tags.contains(tag)))
+            && !self.exclude.iter().any(|tag| tags.contains(tag))
+    }
+
+    fn report(&self, dropped: &[TwitterApiResponseData]) -> String {
+        format!(
+            "#️⃣  Dropped {} tweets not matching the hashtags",
+            dropped.len()
+        )
+    }
+}
+
+/// Client-side keyword gate on the content of the tweets, complementing the
+/// search query. Plain keywords are escaped so both modes share the matching
+struct KeywordFilter {
+    all: Vec<Regex>,
+    any: Vec<Regex>,
+}
+
+impl KeywordFilter {
+    fn new(all: &[String], any: &[String], regex: bool) -> Result<KeywordFilter, String> {
+        let compile = |keywords: &[String]| {
+            keywords
+                .iter()
+                .map(|keyword| {
+                    let pattern = if regex {
+                        keyword.to_string()
+                    } else {
+                        regex::escape(keyword)
+                    };
+                    RegexBuilder::new(&pattern)
+                        .case_insensitive(true)
+                        .build()
+                        .map_err(|e| format!("🙅 `{}` is not a valid regex: {}", keyword, e))
+                })
+                .collect::<Result<Vec<Regex>, String>>()
+        };
+        Ok(KeywordFilter {
+            all: compile(all)?,
+            any: compile(any)?,
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.all.is_empty() && self.any.is_empty()
+    }
+}
+
+impl TweetFilter for KeywordFilter {
+    fn keep(&self, tweet: &TwitterApiResponseData) -> bool {
+        let text = &tweet.text;
+        self.all.iter().all(|keyword| keyword.is_match(text))
+            && (self.any.is_empty() || self.any.iter().any(|keyword| keyword.is_match(text)))
+    }
+
+    fn report(&self, dropped: &[TwitterApiResponseData]) -> String {
+        format!(
+            "🔎 Dropped {} tweets not matching the keywords",
+            dropped.len()
+        )
+    }
+}
+
+/// Randomly drops tweets of the majority sentiment so there are `positive`
+/// positive tweets for every `negative` ones, keeping their original order
+fn balance_tweets(
+    tweets: Vec<HappyTweet>,
+    (positive, negative): (f64, f64),
+    seed: u64,
+) -> Vec<HappyTweet> {
+    let positives = tweets.iter().filter(|tweet| tweet.is_positive()).count();
+    let negatives = tweets.len() - positives;
+    let (majority, keep) = if positives as f64 * negative > negatives as f64 * positive {
+        (true, negatives as f64 * positive / negative)
+    } else {
+        (false, positives as f64 * negative / positive)
+    };
+
+    let members = (0..tweets.len())
+        .filter(|&i| tweets[i].is_positive() == majority)
+        .collect::<Vec<usize>>();
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut selected = vec![true; tweets.len()];
+    for &i in &members {
+        selected[i] = false;
+    }
+    for i in index::sample(&mut rng, members.len(), keep.floor() as usize) {
+        selected[members[i]] = true;
+    }
+    tweets
+        .into_iter()
+        .zip(selected)
+        .filter_map(|(tweet, selected)| selected.then_some(tweet))
+        .collect()
+}
+
+/// Uniformly selects `n` tweets keeping their original order
+fn sample_tweets(tweets: Vec<HappyTweet>, n: usize, seed: u64) -> Vec<HappyTweet> {
+    if tweets.len() <= n {
+        return tweets;
+    }
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut selected = vec![false; tweets.len()];
+    for i in index::sample(&mut rng, tweets.len(), n) {
+        selected[i] = true;
+    }
+    tweets
+        .into_iter()
+        .zip(selected)
+        .filter_map(|(tweet, selected)| selected.then_some(tweet))
+        .collect()
+}
+
+/// Checks that `created_at` is a valid RFC3339 date and reformats it if a
+/// `date_format` is given
+fn format_created_at(
+    created_at: &str,
+    date_format: Option<&str>,
+    timezone: Option<Tz>,
+) -> Result<String, String> {
+    let date = DateTime::parse_from_rfc3339(created_at)
+        .map_err(|_| format!("🙅 Twitter returned an invalid date: `{}`", created_at))?;
+    Ok(match (date_format, timezone) {
+        (Some(date_format), Some(timezone)) => date
+            .with_timezone(&timezone)
+            .format(date_format)
+            .to_string(),
+        (Some(date_format), None) => date.with_timezone(&Utc).format(date_format).to_string(),
+        (None, Some(timezone)) => date
+            .with_timezone(&timezone)
+            .to_rfc3339_opts(SecondsFormat::Millis, false),
+        (None, None) => created_at.to_string(),
+    })
+}
+
+/// Checks the window against what the recent search accepts at `now`: from 7
+/// days ago, with a minute of margin for the request to reach Twitter, to 10
+/// seconds ago. The errors tell the range allowed instead of letting Twitter
+/// answer a bare 400
+fn validate_time_window(window: &TimeWindow, now: DateTime<Utc>) -> Result<(), String> {
+    let earliest = now - chrono::Duration::days(RECENT_SEARCH_DAYS) + RECENT_SEARCH_MARGIN;
+    let latest = now - END_TIME_MARGIN;
+    let date = |date: DateTime<Utc>| date.to_rfc3339_opts(SecondsFormat::Secs, true);
+    let allowed = format!(
+        "the recent search allows from {} to {}",
+        date(earliest),
+        date(latest)
+    );
+    if let (Some(since), Some(until)) = (window.since, window.until) {
+        if since == until {
+            return Err(format!(
+                "🙅 `--since` and `--until` are both {}, the window is empty. Set `--until` after `--since`",
+                date(since)
+            ));
+        }
+        if since > until {
+            return Err(format!(
+                "🙅 `--since` ({}) is after `--until` ({}), swap them",
+                date(since),
+                date(until)
+            ));
+        }
+    }
+    if let Some(since) = window.since {
+        if since < earliest {
+            return Err(format!(
+                "🙅 `--since` ({}) is older than the last {} days, {}",
+                date(since),
+                RECENT_SEARCH_DAYS,
+                allowed
+            ));
+        }
+        if since > latest {
+            return Err(format!(
+                "🙅 `--since` ({}) is in the future, {}",
+                date(since),
+                allowed
+            ));
+        }
+    }
+    if let Some(until) = window.until {
+        if until < earliest {
+            return Err(format!(
+                "🙅 `--until` ({}) is older than the last {} days, {}",
+                date(until),
+                RECENT_SEARCH_DAYS,
+                allowed
+            ));
+        }
+        if until > latest {
+            return Err(format!(
+                "🙅 `--until` ({}) is too recent, {}. Leave it out to search up to now",
+                date(until),
+                allowed
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Parses a date of `--since` or `--until`. Dates without an offset are in
+/// `timezone`, and a day alone means its midnight
+fn parse_local_date(date: &str, timezone: Tz) -> Result<DateTime<Utc>, String> {
+    if let Ok(date) = DateTime::parse_from_rfc3339(date) {
+        return Ok(date.with_timezone(&Utc));
+    }
+    let naive = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(date, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .ok()
+                .and_then(|day| day.and_hms_opt(0, 0, 0))
+        })
+        .ok_or_else(|| {
+            format!(
+                "🙅 `{}` is not a valid date, use \"YYYY-MM-DD\", \"YYYY-MM-DD HH:MM\" or RFC3339",
+                date
+            )
+        })?;
+    timezone
+        .from_local_datetime(&naive)
+        .earliest()
+        .map(|date| date.with_timezone(&Utc))
+        .ok_or_else(|| format!("🙅 `{}` doesn't exist in {}", date, timezone))
+}
+
+/// Message type for internal channel, passing around texts, a sender to tell
+/// when the runner starts working on them and the return value sender
+type Message = (
+    Vec<String>,
+    oneshot::Sender<()>,
+    oneshot::Sender<Vec<Result<Sentiment, String>>>,
+);
+
+thread_local! {
+    /// Whether a panic of this thread is caught by `isolate`
+    static ISOLATING: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+
+/// Runs `f`, turning a panic into the error of its message
+fn isolate<T>(f: impl FnOnce() -> T) -> Result<T, String> {
+    ISOLATING.with(|isolating| isolating.set(true));
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    ISOLATING.with(|isolating| isolating.set(false));
+    result.map_err(|payload| {
+        payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| String::from("the model panicked"))
+    })
+}
+
+/// Keeps the panics caught by `isolate` out of the terminal, they are
+/// reported with the tweet they failed on
+fn quiet_isolated_panics() {
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        if !ISOLATING.with(std::cell::Cell::get) {
+            hook(info);
+        }
+    }));
+}
+
+/// Number of texts `batch_timeout` is meant for, bigger batches get a
+/// proportionally longer timeout
+const BATCH_TIMEOUT_SIZE: usize = 100;
+
+/// Builds the model to classify the tweets with, checking up front that the
+/// files of a custom model are there and that its positive label exists
+fn model_config(args: &Arguments) -> Result<ModelConfig, String> {
+    let device = args.device.tch_device();
+    let path = match &args.model_path {
+        Some(path) => path,
+        None => return Ok(args.model.config(device)),
+    };
+
+    let resource = |name: &str| {
+        let local_path = path.join(name);
+        if local_path.exists() {
+            Ok(Resource::Local(LocalResource { local_path }))
+        } else {
+            Err(format!(
+                "🙅 The model in `{}` doesn't have a `{}`",
+                path.display(),
+                name
+            ))
+        }
+    };
+    let config_resource = resource("config.json")?;
+    let contents = std::fs::read_to_string(path.join("config.json")).map_err(|e| e.to_string())?;
+    let labels: ModelLabels = serde_json::from_str(&contents)
+        .map_err(|e| format!("🙅 Can't read the labels of the model: {}", e))?;
+    let positive_label = args.positive_label.as_deref().unwrap_or("1");
+    let positive_id = labels
+        .id2label
+        .iter()
+        .find(|(id, label)| id.as_str() == positive_label || label.as_str() == positive_label)
+        .and_then(|(id, _)| id.parse::<i64>().ok())
+        .ok_or_else(|| {
+            let mut available: Vec<String> = labels
+                .id2label
+                .iter()
+                .map(|(id, label)| format!("{} ({})", label, id))
+                .collect();
+            available.sort();
+            format!(
+                "🙅 The model has no `{}` label. Its labels are: {}",
+                positive_label,
+                available.join(", ")
+            )
+        })?;
+
+    Ok(ModelConfig {
+        config: SentimentConfig {
+            model_type: args.model_type.into(),
+            model_resource: resource("rust_model.ot")?,
+            config_resource,
+            vocab_resource: resource("vocab.txt").or_else(|_| resource("vocab.json"))?,
+            merges_resource: resource("merges.txt").ok(),
+            lower_case: args.model_lower_case,
+            strip_accents: None,
+            add_prefix_space: None,
+            device,
+        },
+        positive_id,
+        id: format!(
+            "{:?}:{}:{}:{}",
+            args.model_type,
+            path.canonicalize()
+                .unwrap_or_else(|_| path.clone())
+                .display(),
+            positive_id,
+            args.model_lower_case
+        ),
+    })
+}
+
+/// Labels of the classes in the `config.json` of a model
+#[derive(Debug, Deserialize)]
+struct ModelLabels {
+    id2label: HashMap<String, String>,
+}
+
+/// Sentiment model to load and the id of the class kept as positive, every
+/// other class is considered negative
+pub struct ModelConfig {
+    config: SentimentConfig,
+    positive_id: i64,
+    /// Identifies the model and how its classes are read, for the sentiment
+    /// cache
+    id: String,
+}
+
+/// Sentiment classifier spawned the first time there are tweets to classify,
+/// so a bad token or a search finding nothing fails without loading the model
+struct LazyClassifier {
+    model: Option<ModelConfig>,
+    buffer_size: usize,
+    batch_timeout: Option<Duration>,
+    /// Texts are cut to this many bytes before being classified or cached
+    max_content_bytes: usize,
+    /// Longer texts are classified in windows of `max_content_bytes` instead
+    windows: Option<Windows>,
+    /// Texts are normalized before being cut, so `--raw-content` still
+    /// classifies them normalized
+    normalizer: Option<ContentNormalizer>,
+    /// Emoji are removed from the texts after normalizing them
+    strip_emoji: bool,
+    classifier: Option<SentimentClassifier>,
+    cache: Option<SentimentCache>,
+    ensemble: Option<Box<Ensemble>>,
+}
+
+/// Second model of `--ensemble`. A tweet is only positive when both models
+/// find it positive with at least `threshold`, and its score is the lowest
+/// probability of being positive of both
+struct Ensemble {
+    classifier: LazyClassifier,
+    threshold: f64,
+    /// Tweets classified by both models, and how many got the same polarity
+    compared: usize,
+    agreed: usize,
+}
+
+impl Ensemble {
+    fn combine(&mut self, first: Sentiment, second: Sentiment) -> Sentiment {
+        self.compared += 1;
+        if first.polarity == second.polarity {
+            self.agreed += 1;
+        }
+        let happiness = happiness(&first).min(happiness(&second));
+        if first.polarity == SentimentPolarity::Positive
+            && second.polarity == SentimentPolarity::Positive
+            && happiness >= self.threshold
+        {
+            Sentiment {
+                polarity: SentimentPolarity::Positive,
+                score: happiness,
+            }
+        } else {
+            Sentiment {
+                polarity: SentimentPolarity::Negative,
+                score: 1.0 - happiness,
+            }
+        }
+    }
+
+    /// Share of the tweets both models gave the same polarity, once any was
+    /// classified by both
+    fn agreement(&self) -> Option<f64> {
+        (self.compared > 0).then(|| self.agreed as f64 / self.compared as f64)
+    }
+}
+
+/// `--window`: the texts longer than `--max-content-bytes` are classified in
+/// windows starting every `stride` bytes
+#[derive(Clone, Copy, Debug)]
+struct Windows {
+    stride: usize,
+    aggregate: WindowAggregate,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq)]
+enum WindowAggregate {
+    /// Mean probability of being positive of the windows
+    Mean,
+    /// Window furthest from neutral, so a single strong passage decides
+    Max,
+}
+
+impl WindowAggregate {
+    fn aggregate(self, windows: &[Sentiment]) -> Sentiment {
+        let happiness = match self {
+            WindowAggregate::Mean => {
+                windows.iter().map(happiness).sum::<f64>() / windows.len() as f64
+            }
+            WindowAggregate::Max => windows
+                .iter()
+                .map(happiness)
+                .max_by(|a, b| (a - 0.5).abs().total_cmp(&(b - 0.5).abs()))
+                .unwrap_or(0.5),
+        };
+        if happiness >= 0.5 {
+            Sentiment {
+                polarity: SentimentPolarity::Positive,
+                score: happiness,
+            }
+        } else {
+            Sentiment {
+                polarity: SentimentPolarity::Negative,
+                score: 1.0 - happiness,
+            }
+        }
+    }
+}
+
+/// Splits `text` in windows of at most `size` bytes starting every `stride`
+/// bytes, both moved back to a char boundary, the last one reaching the end
+fn text_windows(text: &str, size: usize, stride: usize) -> Vec<&str> {
+    let mut windows = Vec::new();
+    let mut start = 0;
+    loop {
+        let window = truncate_bytes(&text[start..], size);
+        windows.push(window);
+        if start + window.len() >= text.len() {
+            return windows;
+        }
+        let mut next = start + stride;
+        while !text.is_char_boundary(next) {
+            next -= 1;
+        }
+        // A stride shorter than a char still moves on
+        start = if next > start {
+            next
+        } else {
+            start + text[start..].chars().next().map_or(1, char::len_utf8)
+        };
+    }
+}
+
+impl LazyClassifier {
+    /// Classifier of the model, device and text options of the arguments,
+    /// for the commands classifying texts one at a time
+    fn new(args: &Arguments) -> Result<LazyClassifier, String> {
+        if let Some(cache_dir) = &args.cache_dir {
+            env::set_var(MODEL_CACHE_ENV_NAME, cache_dir);
+        }
+        Ok(LazyClassifier {
+            model: Some(model_config(args)?),
+            buffer_size: 1,
+            batch_timeout: None,
+            max_content_bytes: args.max_content_bytes,
+            windows: None,
+            normalizer: ContentNormalizer::new(args),
+            strip_emoji: args.strip_emoji,
+            classifier: None,
+            cache: None,
+            ensemble: None,
+        })
+    }
+
+    /// Classifies the texts, cut to `max_content_bytes` or else split in
+    /// `windows` aggregated back. A text fails if any of its windows does
+    async fn predict(&mut self, texts: Vec<String>) -> Result<Vec<Result<Sentiment, String>>> {
+        let texts = match self.normalizer {
+            Some(normalizer) => texts
+                .iter()
+                .map(|text| normalizer.normalize(text))
+                .collect(),
+            None => texts,
+        };
+        let texts = if self.strip_emoji {
+            texts.iter().map(|text| strip_emoji(text)).collect()
+        } else {
+            texts
+        };
+        let windows = match self.windows {
+            Some(windows) => windows,
+            None => {
+                let texts = texts
+                    .into_iter()
+                    .map(|text| match truncate_bytes(&text, self.max_content_bytes) {
+                        truncated if truncated.len() < text.len() => truncated.to_string(),
+                        _ => text,
+                    })
+                    .collect::<Vec<String>>();
+                return self.predict_cached(texts).await;
+            }
+        };
+        let split = texts
+            .iter()
+            .map(|text| text_windows(text, self.max_content_bytes, windows.stride))
+            .collect::<Vec<Vec<&str>>>();
+        let pieces = split.iter().flatten().map(|window| window.to_string());
+        let mut sentiments = self.predict_cached(pieces.collect()).await?.into_iter();
+        Ok(split
+            .iter()
+            .map(|text| {
+                // The windows of a text are all taken before looking at them
+                let results = sentiments.by_ref().take(text.len()).collect::<Vec<_>>();
+                let sentiments = results.into_iter().collect::<Result<Vec<Sentiment>, _>>()?;
+                Ok(windows.aggregate.aggregate(&sentiments))
+            })
+            .collect())
+    }
+
+    /// Classifies the texts, only running the model on the ones missing from
+    /// the cache. The texts the model failed on are not cached, so they are
+    /// tried again by the next runs
+    async fn predict_cached(
+        &mut self,
+        texts: Vec<String>,
+    ) -> Result<Vec<Result<Sentiment, String>>> {
+        let cache = match &mut self.cache {
+            Some(cache) => cache,
+            None => return self.classify(texts).await,
+        };
+        let hashes: Vec<String> = texts.iter().map(|text| text_hash(text)).collect();
+        let missing: Vec<String> = texts
+            .into_iter()
+            .zip(&hashes)
+            .filter(|(_, hash)| !cache.sentiments.contains_key(*hash))
+            .map(|(text, _)| text)
+            .collect();
+        let mut failed = HashMap::new();
+        if !missing.is_empty() {
+            let missing_hashes: Vec<String> = missing.iter().map(|text| text_hash(text)).collect();
+            let sentiments = self.classify(missing).await?;
+            let cache = self.cache.as_mut().expect("checked above");
+            for (hash, sentiment) in missing_hashes.into_iter().zip(sentiments) {
+                match sentiment {
+                    Ok(sentiment) => {
+                        cache.sentiments.insert(hash, sentiment);
+                    }
+                    Err(reason) => {
+                        failed.insert(hash, reason);
+                    }
+                }
+            }
+        }
+        let cache = self.cache.as_ref().expect("checked above");
+        Ok(hashes
+            .iter()
+            .map(|hash| match cache.sentiments.get(hash) {
+                Some(sentiment) => Ok(Sentiment {
+                    polarity: match sentiment.polarity {
+                        SentimentPolarity::Positive => SentimentPolarity::Positive,
+                        SentimentPolarity::Negative => SentimentPolarity::Negative,
+                    },
+                    score: sentiment.score,
+                }),
+                None => Err(failed[hash].clone()),
+            })
+            .collect())
+    }
+
+    /// Runs the model on the texts, combined with the second model of the
+    /// ensemble if any. A text either model fails on fails
+    async fn classify(&mut self, texts: Vec<String>) -> Result<Vec<Result<Sentiment, String>>> {
+        if self.ensemble.is_none() {
+            return self.get().predict(texts).await;
+        }
+        let firsts = self.get().predict(texts.clone()).await?;
+        let ensemble = self.ensemble.as_mut().expect("checked above");
+        let seconds = ensemble.classifier.get().predict(texts).await?;
+        Ok(firsts
+            .into_iter()
+            .zip(seconds)
+            .map(|sentiments| match sentiments {
+                (Ok(first), Ok(second)) => Ok(ensemble.combine(first, second)),
+                (Err(reason), _) | (_, Err(reason)) => Err(reason),
+            })
+            .collect())
+    }
+
+    /// Label of the spinner while classifying. The model is loaded by the
+    /// first batch
+    fn phase(&self) -> &'static str {
+        if self.classifier.is_none() {
+            "Loading model…"
+        } else {
+            "Classifying…"
+        }
+    }
+
+    fn get(&mut self) -> &SentimentClassifier {
+        self.classifier.get_or_insert_with(|| {
+            let model = self.model.take().expect("the classifier is only spawned once");
+            if let Resource::Remote(resource) = &model.config.model_resource {
+                let cache_dir = model_cache_dir();
+                if !cache_dir.join(&resource.cache_subdir).exists() {
+                    println!(
+                        "⏳ The sentiment model is downloaded to {} on the first run, it can take a few minutes",
+                        cache_dir.display()
+                    );
+                }
+            }
+            let (_handle, classifier) =
+                SentimentClassifier::spawn(model, self.buffer_size, self.batch_timeout);
+            classifier
+        })
+    }
+}
+
+/// Runner for sentiment classification
+#[derive(Debug, Clone)]
+pub struct SentimentClassifier {
+    sender: mpsc::SyncSender<Message>,
+    batch_timeout: Option<Duration>,
+}
+
+impl SentimentClassifier {
+    /// Spawn a classifier on a separate thread and return a classifier instance
+    /// to interact with it. The model is built from `model`. Up to
+    /// `buffer_size` predictions can be queued before `predict` blocks.
+    /// Predictions taking longer than `batch_timeout` (for every 100 texts)
+    /// fail
+    pub fn spawn(
+        model: ModelConfig,
+        buffer_size: usize,
+        batch_timeout: Option<Duration>,
+    ) -> (JoinHandle<Result<()>>, SentimentClassifier) {
+        let (sender, receiver) = mpsc::sync_channel(buffer_size);
+        quiet_isolated_panics();
+        let handle = thread::spawn(move || Self::runner(model, receiver));
+        (
+            handle,
+            SentimentClassifier {
+                sender,
+                batch_timeout,
+            },
+        )
+    }
+
+    /// The classification runner itself
+    fn runner(model: ModelConfig, receiver: mpsc::Receiver<Message>) -> Result<()> {
+        let positive_id = model.positive_id;
+        tch::manual_seed(MODEL_SEED);
+        // Needs to be in sync runtime, async doesn't work
+        let model = SequenceClassificationModel::new(model.config)?;
+
+        while let Ok((texts, started, sender)) = receiver.recv() {
+            // The receivers are gone if the prediction timed out
+            let _ = started.send(());
+            let texts: Vec<&str> = texts.iter().map(String::as_str).collect();
+            let sentiment = |label: Label| Sentiment {
+                polarity: if label.id == positive_id {
+                    SentimentPolarity::Positive
+                } else {
+                    SentimentPolarity::Negative
+                },
+                score: label.score,
+            };
+            // The model panics on the texts it can't handle, failing the whole
+            // batch, so its texts are classified one by one to isolate them
+            let sentiments = match isolate(|| model.predict(&texts)) {
+                Ok(labels) if labels.len() == texts.len() => {
+                    labels.into_iter().map(sentiment).map(Ok).collect()
+                }
+                _ => texts
+                    .iter()
+                    .map(|text| {
+                        isolate(|| model.predict([*text]))?
+                            .pop()
+                            .map(sentiment)
+                            .ok_or_else(|| String::from("the model returned no label"))
+                    })
+                    .collect(),
+            };
+            let _ = sender.send(sentiments);
+        }
+
+        Ok(())
+    }
+
+    /// Make the runner predict a sample and return the result. The timeout
+    /// only starts once the runner picks the sample, so loading the model
+    /// doesn't count. A single runner handles the samples in the order they
+    /// are sent, so the sentiments are always in the order of the texts. A
+    /// text the model fails on gets the reason instead of a sentiment
+    pub async fn predict(&self, texts: Vec<String>) -> Result<Vec<Result<Sentiment, String>>> {
+        let batch_size = texts.len();
+        let (started_sender, started) = oneshot::channel();
+        let (sender, receiver) = oneshot::channel();
+        task::block_in_place(|| self.sender.send((texts, started_sender, sender)))?;
+        started.await?;
+        match self.batch_timeout {
+            Some(batch_timeout) => {
+                let timeout = batch_timeout
+                    .mul_f64(batch_size.max(BATCH_TIMEOUT_SIZE) as f64 / BATCH_TIMEOUT_SIZE as f64);
+                match time::timeout(timeout, receiver).await {
+                    Ok(sentiments) => Ok(sentiments?),
+                    Err(_) => Err(anyhow!(
+                        "🙅 Classifying {} tweets took more than {} seconds",
+                        batch_size,
+                        timeout.as_secs()
+                    )),
+                }
+            }
+            None => Ok(receiver.await?),
+        }
+    }
+}
+
+/// Texts to score against the topic, with the sender of their scores
+type TopicMessage = (Vec<String>, oneshot::Sender<Result<Vec<f64>, String>>);
+
+/// Runner scoring how much tweets are about `topic` with a zero-shot model,
+/// spawned the first time there are tweets to score
+struct TopicClassifier {
+    topic: String,
+    device: Device,
+    sender: Option<mpsc::Sender<TopicMessage>>,
+}
+
+impl TopicClassifier {
+    fn new(topic: String, device: Device) -> TopicClassifier {
+        TopicClassifier {
+            topic,
+            device,
+            sender: None,
+        }
+    }
+
+    /// Probabilities of the texts being about the topic, from 0 to 1
+    async fn scores(&mut self, texts: Vec<String>) -> Result<Vec<f64>, String> {
+        let (sender, receiver) = oneshot::channel();
+        self.sender()
+            .send((texts, sender))
+            .map_err(|_| String::from("🙅 The topic runner stopped"))?;
+        receiver
+            .await
+            .map_err(|_| String::from("🙅 The topic runner stopped"))?
+            .map_err(|e| format!("🙅 Can't score the topic of the tweets: {}", e))
+    }
+
+    fn sender(&mut self) -> &mpsc::Sender<TopicMessage> {
+        let (topic, device) = (self.topic.clone(), self.device);
+        self.sender.get_or_insert_with(|| {
+            let (sender, receiver) = mpsc::channel();
+            thread::spawn(move || Self::runner(topic, device, receiver));
+            sender
+        })
+    }
+
+    /// Like the sentiment runner, it needs to run outside of the async
+    /// runtime. The topic is scored on its own, as a multi-label class, since
+    /// a single label would always get all of the probability
+    fn runner(topic: String, device: Device, receiver: mpsc::Receiver<TopicMessage>) {
+        tch::manual_seed(MODEL_SEED);
+        let model = ZeroShotClassificationModel::new(ZeroShotClassificationConfig {
+            device,
+            ..Default::default()
+        })
+        .map_err(|e| e.to_string());
+        while let Ok((texts, sender)) = receiver.recv() {
+            let scores = model.as_ref().map_err(Clone::clone).map(|model| {
+                let texts = texts.iter().map(String::as_str).collect::<Vec<&str>>();
+                model
+                    .predict_multilabel(&texts, [topic.as_str()], None, 128)
+                    .iter()
+                    .map(|labels| labels.first().map_or(0.0, |label| label.score))
+                    .collect()
+            });
+            let _ = sender.send(scores);
+        }
+    }
+}
+
+/// Texts to translate from a language, with the sender of their translations
+type TranslationMessage = (
+    Vec<String>,
+    Language,
+    oneshot::Sender<Result<Vec<String>, String>>,
+);
+
+/// Runner translating tweets to English with M2M100, spawned the first time
+/// there is a tweet to translate
+struct Translator {
+    device: Device,
+    sender: Option<mpsc::Sender<TranslationMessage>>,
+}
+
+impl Translator {
+    fn new(device: Device) -> Translator {
+        Translator {
+            device,
+            sender: None,
+        }
+    }
+
+    /// Translates the tweets Twitter didn't detect as English and that aren't
+    /// in `translations` yet, grouped by language. Tweets in a language the
+    /// model doesn't know are classified as they are
+    async fn translate_tweets(
+        &mut self,
+        tweets: &[&TwitterApiResponseData],
+        translations: &mut HashMap<String, String>,
+    ) -> Result<(), String> {
+        let mut by_language: HashMap<Language, Vec<&TwitterApiResponseData>> = HashMap::new();
+        for &tweet in tweets {
+            if translations.contains_key(&tweet.id) {
+                continue;
+            }
+            let code = match &tweet.lang {
+                Some(lang) => lang.split('-').next().unwrap_or(lang),
+                None => continue,
+            };
+            if let Some(language) = M2M100SourceLanguages::M2M100_418M.iter().find(|language| {
+                **language != Language::English && language.get_iso_639_1_code() == code
+            }) {
+                by_language.entry(*language).or_default().push(tweet);
+            }
+        }
+        if by_language.is_empty() {
+            return Ok(());
+        }
+
+        println!(
+            "🌍 Translating {} tweets to English...",
+            by_language.values().map(Vec::len).sum::<usize>()
+        );
+        for (language, tweets) in by_language {
+            let texts = tweets.iter().map(|tweet| tweet.text.to_owned()).collect();
+            let (sender, receiver) = oneshot::channel();
+            self.sender()
+                .send((texts, language, sender))
+                .map_err(|_| String::from("🙅 The translation runner stopped"))?;
+            let translated = receiver
+                .await
+                .map_err(|_| String::from("🙅 The translation runner stopped"))?
+                .map_err(|e| format!("🙅 Can't translate the tweets: {}", e))?;
+            for (tweet, translation) in tweets.iter().zip(translated) {
+                translations.insert(tweet.id.to_owned(), translation);
+            }
+        }
+        Ok(())
+    }
+
+    fn sender(&mut self) -> &mpsc::Sender<TranslationMessage> {
+        let device = self.device;
+        self.sender.get_or_insert_with(|| {
+            let (sender, receiver) = mpsc::channel();
+            thread::spawn(move || Self::runner(device, receiver));
+            sender
+        })
+    }
+
+    /// Like the sentiment runner, it needs to run outside of the async
+    /// runtime. A model that can't be loaded fails every translation
+    fn runner(device: Device, receiver: mpsc::Receiver<TranslationMessage>) {
+        tch::manual_seed(MODEL_SEED);
+        let model = TranslationModelBuilder::new()
+            .with_model_type(ModelType::M2M100)
+            .with_source_languages(M2M100SourceLanguages::M2M100_418M.to_vec())
+            .with_target_languages([Language::English])
+            .with_device(device)
+            .create_model()
+            .map_err(|e| e.to_string());
+        while let Ok((texts, language, sender)) = receiver.recv() {
+            let translations = model.as_ref().map_err(Clone::clone).and_then(|model| {
+                model
+                    .translate(&texts, language, Language::English)
+                    .map_err(|e| e.to_string())
+            });
+            let _ = sender.send(translations);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Path of a fresh file of the temporary directory, removed first in case
+    /// a previous run left it
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        let dir = env::temp_dir().join(format!("happy-tweet-tests-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    fn happy_tweet(id: &str) -> HappyTweet {
+        serde_json::from_value(json!({
+            "user": {"username": "banana", "profile_image_url": "https://pbs.twimg.com/banana.png"},
+            "tweet": {
+                "url": format!("https://twitter.com/banana/status/{}", id),
+                "content": format!("I love bananas {}", id),
+                "created_at": "2022-04-20T16:20:00.000Z",
+            },
+        }))
+        .unwrap()
+    }
+
+    fn write_options() -> WriteOptions {
+        WriteOptions {
+            overwrite: false,
+            envelope: None,
+            flush_every: 0,
+            recover: false,
+            color: false,
+            locale: Locale::C,
+            fields: Vec::new(),
+            encoding: OutputEncoding::Utf8,
+            indent: String::from("  "),
+            next_token: None,
+        }
+    }
+
+    fn write(path: &std::path::Path, ids: &[&str], format: OutputFormat) -> Result<usize, String> {
+        let tweets = ids.iter().map(|id| happy_tweet(id)).collect::<Vec<_>>();
+        let tweets = tweets.iter().collect::<Vec<&HappyTweet>>();
+        write_tweets(&tweets, path, format, &write_options()).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn appends_to_an_output_of_the_same_format() {
+        for (name, format) in [
+            ("same.json", OutputFormat::Json),
+            ("same.ndjson", OutputFormat::Ndjson),
+        ] {
+            let path = temp_path(name);
+            assert_eq!(write(&path, &["1"], format), Ok(1));
+            assert_eq!(write(&path, &["1", "2"], format), Ok(2));
+        }
+    }
+
+    #[test]
+    fn refuses_to_write_another_format_over_tweets() {
+        let combinations = [
+            (OutputFormat::Json, OutputFormat::Markdown),
+            (OutputFormat::Json, OutputFormat::Ndjson),
+            (OutputFormat::Ndjson, OutputFormat::Json),
+            (OutputFormat::Ndjson, OutputFormat::Markdown),
+        ];
+        for (existing, format) in combinations {
+            // The extension of the existing format, so only the content tells
+            let path = temp_path(&format!("mismatch-{:?}-{:?}.out", existing, format));
+            write(&path, &["1"], existing).unwrap();
+            let before = std::fs::read_to_string(&path).unwrap();
+            let error = write(&path, &["2"], format).unwrap_err();
+            assert!(error.contains("would lose them"), "{}", error);
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), before);
+        }
+    }
+
+    #[test]
+    fn refuses_to_write_fields_over_tweets() {
+        let path = temp_path("fields.json");
+        write(&path, &["1"], OutputFormat::Json).unwrap();
+        let tweet = happy_tweet("2");
+        let options = WriteOptions {
+            fields: vec![Field::Url],
+            ..write_options()
+        };
+        let error = write_tweets(&[&tweet], &path, OutputFormat::Json, &options).unwrap_err();
+        assert!(error.to_string().contains("only some fields"));
+    }
+
+    #[test]
+    fn refuses_to_append_tweets_to_markdown() {
+        let path = temp_path("markdown.json");
+        write(&path, &["1"], OutputFormat::Markdown).unwrap();
+        assert!(write(&path, &["2"], OutputFormat::Json).is_err());
+        assert!(write(&path, &["2"], OutputFormat::Ndjson).is_err());
+        // Markdown over Markdown is replaced, as it holds no tweets
+        assert_eq!(write(&path, &["2"], OutputFormat::Markdown), Ok(1));
+    }
+
+    #[test]
+    fn overwrite_replaces_an_output_of_another_format() {
+        let path = temp_path("overwrite.json");
+        write(&path, &["1", "2"], OutputFormat::Json).unwrap();
+        let tweet = happy_tweet("3");
+        let options = WriteOptions {
+            overwrite: true,
+            ..write_options()
+        };
+        write_tweets(&[&tweet], &path, OutputFormat::Markdown, &options).unwrap();
+        assert!(std::fs::read_to_string(&path)
+            .unwrap()
+            .starts_with("# Happy tweets"));
+    }
+
+    #[test]
+    fn writes_a_default_file_in_an_output_directory() {
+        let dir = temp_path("outputs");
+        std::fs::create_dir_all(&dir).unwrap();
+        let dir_arg = dir.to_str().unwrap();
+        let args = Arguments::parse_from([
+            "happy-tweet",
+            "#banana",
+            "-o",
+            dir_arg,
+            "-o",
+            dir_arg,
+            "-f",
+            "json",
+            "-f",
+            "markdown",
+        ]);
+        assert_eq!(
+            resolve_outputs(&args),
+            vec![
+                (dir.join("happy-tweets.json"), OutputFormat::Json),
+                (dir.join("happy-tweets.md"), OutputFormat::Markdown),
+            ]
+        );
+    }
+
+    #[test]
+    fn drops_the_sentiment_cache_of_another_model() {
+        let path = temp_path("cache.json");
+        let mut cache = SentimentCache::load(&path, "distilbert").unwrap();
+        let sentiment = Sentiment {
+            polarity: SentimentPolarity::Positive,
+            score: 0.9,
+        };
+        cache
+            .sentiments
+            .insert(text_hash("I love bananas"), sentiment);
+        cache.save().unwrap();
+        assert!(!std::path::PathBuf::from(format!("{}.tmp", path.display())).exists());
+
+        let other = SentimentCache::load(&path, "twitter-roberta").unwrap();
+        assert!(other.sentiments.is_empty());
+        assert_eq!(other.model, "twitter-roberta");
+        let same = SentimentCache::load(&path, "distilbert").unwrap();
+        assert_eq!(same.sentiments.len(), 1);
+    }
+
+    fn classifier() -> LazyClassifier {
+        LazyClassifier {
+            model: Some(ModelPreset::English.config(Device::Cpu)),
+            buffer_size: 1,
+            batch_timeout: None,
+            max_content_bytes: DEFAULT_MAX_CONTENT_BYTES,
+            windows: None,
+            normalizer: None,
+            strip_emoji: false,
+            classifier: None,
+            cache: None,
+            ensemble: None,
+        }
+    }
+
+    /// Polarity and score of each text, comparable across runs
+    async fn scores(classifier: &mut LazyClassifier, texts: &[&str]) -> Vec<(bool, f64)> {
+        let texts = texts.iter().map(|text| text.to_string()).collect();
+        classifier
+            .predict(texts)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(Result::unwrap)
+            .map(|sentiment| {
+                (
+                    sentiment.polarity == SentimentPolarity::Positive,
+                    sentiment.score,
+                )
+            })
+            .collect()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[ignore = "downloads the sentiment model"]
+    async fn classifies_the_same_texts_the_same_way_across_workers() {
+        let texts = [
+            "I love bananas",
+            "This is so sad",
+            "Bananas for breakfast again",
+            "sad and tired",
+        ];
+        let (mut first, mut second) = (classifier(), classifier());
+        let (firsts, seconds) =
+            tokio::join!(scores(&mut first, &texts), scores(&mut second, &texts));
+        assert_eq!(firsts, seconds);
+        assert_eq!(scores(&mut first, &texts).await, firsts);
+    }
+
+    fn api_tweet(id: &str, text: &str) -> TwitterApiResponseData {
+        TwitterApiResponseData {
+            id: id.to_string(),
+            text: text.to_string(),
+            author_id: String::from("42"),
+            created_at: String::from("2022-04-20T16:20:00.000Z"),
+            ..TwitterApiResponseData::default()
+        }
+    }
+
+    fn ids(tweets: &[TwitterApiResponseData]) -> Vec<&str> {
+        tweets.iter().map(|tweet| tweet.id.as_str()).collect()
+    }
+
+    /// Filter of another crate, only keeping the tweets with an even id
+    struct EvenIds;
+
+    impl TweetFilter for EvenIds {
+        fn keep(&self, tweet: &TwitterApiResponseData) -> bool {
+            tweet.id.parse::<u64>().is_ok_and(|id| id % 2 == 0)
+        }
+
+        fn report(&self, dropped: &[TwitterApiResponseData]) -> String {
+            format!("Dropped {} odd tweets", dropped.len())
+        }
+    }
+
+    #[test]
+    fn applies_a_custom_filter_with_the_built_in_ones() {
+        let keywords = KeywordFilter::new(&[String::from("banana")], &[], false).unwrap();
+        let filters: Vec<Box<dyn TweetFilter>> = vec![Box::new(keywords), Box::new(EvenIds)];
+        let tweets = vec![
+            api_tweet("1", "banana bread"),
+            api_tweet("2", "banana split"),
+            api_tweet("3", "apple pie"),
+            api_tweet("4", "apple crumble"),
+        ];
+        let (kept, dropped) = apply_filters(&filters, tweets);
+        assert_eq!(ids(&kept), ["2"]);
+        // Each tweet only counts for the first filter it fails
+        assert_eq!(ids(&dropped[0]), ["3", "4"]);
+        assert_eq!(ids(&dropped[1]), ["1"]);
+        assert_eq!(filters[1].report(&dropped[1]), "Dropped 1 odd tweets");
+    }
+
+    #[test]
+    fn keyword_filter_requires_all_and_any_keywords() {
+        let all = [String::from("banana")];
+        let any = [String::from("bread"), String::from("split")];
+        let filter = KeywordFilter::new(&all, &any, false).unwrap();
+        assert!(filter.keep(&api_tweet("1", "BANANA bread")));
+        assert!(!filter.keep(&api_tweet("2", "banana pie")));
+        assert!(!filter.keep(&api_tweet("3", "bread")));
+        let regex = KeywordFilter::new(&[String::from("^ban+ana$")], &[], true).unwrap();
+        assert!(regex.keep(&api_tweet("4", "bannnana")));
+        assert!(KeywordFilter::new(&[String::from("(")], &[], true).is_err());
+        // Without `--match-regex` the keyword is taken literally
+        assert!(KeywordFilter::new(&[String::from("(")], &[], false).is_ok());
+    }
+
+    #[test]
+    fn hashtag_filter_includes_and_excludes_tags() {
+        let tagged = |id: &str, tags: &[&str]| TwitterApiResponseData {
+            entities: serde_json::from_value(json!({
+                "hashtags": tags.iter().map(|tag| json!({"tag": tag})).collect::<Vec<_>>(),
+            }))
+            .unwrap(),
+            ..api_tweet(id, "")
+        };
+        let filter = HashtagFilter::new(&[String::from("#Banana")], &[String::from("ad")]);
+        assert!(filter.keep(&tagged("1", &["banana"])));
+        assert!(!filter.keep(&tagged("2", &["banana", "Ad"])));
+        assert!(!filter.keep(&tagged("3", &["apple"])));
+        assert!(!filter.keep(&api_tweet("4", "#banana")));
+    }
+
+    #[test]
+    fn unique_words_filter_drops_low_effort_tweets() {
+        let filter = UniqueWordsFilter { min: 2 };
+        assert!(!filter.keep(&api_tweet("1", "🎉🎉🎉 @friend")));
+        assert!(!filter.keep(&api_tweet("2", "yes yes yes")));
+        assert!(filter.keep(&api_tweet("3", "banana bread")));
+        let dropped = [
+            api_tweet("1", "🎉🎉🎉 @friend"),
+            api_tweet("2", "yes yes yes"),
+        ];
+        assert!(filter
+            .report(&dropped)
+            .ends_with("1 of them without any word"));
+    }
+
+    #[test]
+    fn sensitive_filter_keeps_unflagged_tweets() {
+        let flagged = |flag| TwitterApiResponseData {
+            possibly_sensitive: flag,
+            ..api_tweet("1", "banana")
+        };
+        assert!(!SensitiveFilter.keep(&flagged(Some(true))));
+        assert!(SensitiveFilter.keep(&flagged(Some(false))));
+        assert!(SensitiveFilter.keep(&flagged(None)));
+    }
+
+    #[test]
+    fn context_filter_matches_the_annotations() {
+        let annotated = TwitterApiResponseData {
+            context_annotations: serde_json::from_value(json!([{
+                "domain": {"id": "65", "name": "Interests and Hobbies Vertical"},
+                "entity": {"id": "1", "name": "Food"},
+            }]))
+            .unwrap(),
+            ..api_tweet("1", "banana")
+        };
+        assert!(ContextFilter::new(&[String::from("food")]).keep(&annotated));
+        assert!(!ContextFilter::new(&[String::from("sports")]).keep(&annotated));
+        assert!(!ContextFilter::new(&[String::from("food")]).keep(&api_tweet("2", "banana")));
+    }
+
+    #[test]
+    fn enables_the_filters_of_the_arguments_in_order() {
+        let args = Arguments::parse_from([
+            "happy-tweet",
+            "#banana",
+            "--no-sensitive",
+            "--must-contain",
+            "bread",
+            "--min-unique-words",
+            "2",
+        ]);
+        let filters = tweet_filters(&args).unwrap();
+        let dropped = [api_tweet("1", "")];
+        let reports = filters
+            .iter()
+            .map(|filter| filter.report(&dropped))
+            .collect::<Vec<String>>();
+        assert_eq!(reports.len(), 3);
+        assert!(reports[1].starts_with("🧹"), "{:?}", reports);
+        assert!(reports[2].starts_with("🔞"), "{:?}", reports);
+    }
+
+    #[test]
+    fn reads_back_compressed_outputs() {
+        for name in [
+            "round-trip.json.gz",
+            "round-trip.ndjson.zst",
+            "round-trip.json",
+        ] {
+            let path = temp_path(name);
+            let format = OutputFormat::from_path(&path).unwrap();
+            assert_eq!(write(&path, &["1", "2"], format), Ok(2));
+            assert_eq!(write(&path, &["2", "3"], format), Ok(3));
+            let mut ids = read_tweets_file(&path)
+                .unwrap()
+                .iter()
+                .map(|tweet| tweet.id().to_string())
+                .collect::<Vec<String>>();
+            ids.sort();
+            assert_eq!(ids, ["1", "2", "3"], "{}", name);
+            let magic = match Compression::from_path(&path) {
+                Some(Compression::Gzip) => &[0x1f, 0x8b][..],
+                Some(Compression::Zstd) => &[0x28, 0xb5, 0x2f, 0xfd][..],
+                None => b"[",
+            };
+            assert!(std::fs::read(&path).unwrap().starts_with(magic), "{}", name);
+            assert!(!std::path::PathBuf::from(format!("{}.tmp", path.display())).exists());
+        }
+    }
+
+    #[test]
+    fn resumes_from_the_cursor_of_a_partial_output() {
+        let path = temp_path("resume.ndjson");
+        let page = |ids: &[&str], next_token: Option<&str>| {
+            let tweets = ids.iter().map(|id| happy_tweet(id)).collect::<Vec<_>>();
+            let tweets = tweets.iter().collect::<Vec<&HappyTweet>>();
+            let options = WriteOptions {
+                next_token: next_token.map(String::from),
+                ..write_options()
+            };
+            write_tweets(&tweets, &path, OutputFormat::Ndjson, &options).unwrap()
+        };
+        let resume = |path: &std::path::Path| {
+            let (ids, next_token) = read_resume(path).unwrap();
+            let mut ids = ids.into_iter().collect::<Vec<String>>();
+            ids.sort();
+            (ids, next_token)
+        };
+
+        assert_eq!(page(&["1", "2"], Some("page-2")), 2);
+        assert_eq!(
+            resume(&path),
+            (
+                vec![String::from("1"), String::from("2")],
+                Some(String::from("page-2"))
+            )
+        );
+
+        // A page of duplicates still moves the cursor forward
+        assert_eq!(page(&["2"], Some("page-3")), 2);
+        assert_eq!(resume(&path).1, Some(String::from("page-3")));
+
+        // The end of the search clears it
+        assert_eq!(page(&["3"], None), 3);
+        assert_eq!(resume(&path).1, None);
+        assert_eq!(page(&["3"], Some("page-4")), 3);
+        assert_eq!(page(&["3"], None), 3);
+        assert_eq!(resume(&path).1, None);
+        assert_eq!(resume(&path).0.len(), 3);
+        assert_eq!(read_ndjson_file(&path).unwrap().len(), 3);
+        assert_eq!(existing_tweets_format(&path), Some(OutputFormat::Ndjson));
+    }
+
+    #[test]
+    fn resumes_from_the_token_of_an_older_output() {
+        let path = temp_path("resume-old.ndjson");
+        let mut line = serde_json::to_value(happy_tweet("1")).unwrap();
+        line["next_token"] = json!("page-2");
+        std::fs::write(&path, format!("{}\n", line)).unwrap();
+        let (ids, next_token) = read_resume(&path).unwrap();
+        assert_eq!(ids, HashSet::from([String::from("1")]));
+        assert_eq!(next_token, Some(String::from("page-2")));
+    }
+
+    fn window(since: Option<&str>, until: Option<&str>) -> TimeWindow {
+        let date = |date: &str| {
+            DateTime::parse_from_rfc3339(date)
+                .unwrap()
+                .with_timezone(&Utc)
+        };
+        TimeWindow {
+            since: since.map(date),
+            until: until.map(date),
+            since_id: None,
+        }
+    }
+
+    #[test]
+    fn checks_the_time_window_of_the_recent_search() {
+        let now = DateTime::parse_from_rfc3339("2022-04-20T16:20:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let check = |since, until| validate_time_window(&window(since, until), now);
+        let error = |since, until| check(since, until).unwrap_err();
+
+        assert_eq!(check(None, None), Ok(()));
+        assert_eq!(
+            check(Some("2022-04-14T16:20:00Z"), Some("2022-04-20T16:00:00Z")),
+            Ok(())
+        );
+        assert!(error(Some("2022-04-21T00:00:00Z"), None).contains("is in the future"));
+        assert!(error(None, Some("2022-04-20T16:20:00Z")).contains("is too recent"));
+        assert!(
+            error(Some("2022-04-19T00:00:00Z"), Some("2022-04-18T00:00:00Z"))
+                .contains("is after `--until`")
+        );
+        assert!(
+            error(Some("2022-04-19T00:00:00Z"), Some("2022-04-19T00:00:00Z"))
+                .contains("the window is empty")
+        );
+        assert!(error(Some("2022-04-13T16:20:00Z"), None).contains("older than the last 7 days"));
+        assert!(error(None, Some("2022-04-01T00:00:00Z")).contains("older than the last 7 days"));
+        // The minute of margin for the request to reach Twitter
+        assert!(check(Some("2022-04-13T16:20:30Z"), None).is_err());
+        assert_eq!(check(Some("2022-04-13T16:21:00Z"), None), Ok(()));
+    }
+
+    /// Held by the test using the API of `mock_api`, since its url is read
+    /// from the environment shared by all tests
+    static MOCK_API: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+    /// Answers every request of the client with `body`, like the Twitter API,
+    /// until the guard returned is dropped
+    async fn mock_api(body: &'static str) -> tokio::sync::MutexGuard<'static, ()> {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let guard = MOCK_API.lock().await;
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut request = Vec::new();
+                let mut buffer = [0; 1024];
+                while !request.windows(4).any(|end| end == b"\r\n\r\n") {
+                    match socket.read(&mut buffer).await {
+                        Ok(0) | Err(_) => break,
+                        Ok(read) => request.extend_from_slice(&buffer[..read]),
+                    }
+                }
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+        env::set_var(API_URL_ENV_NAME, url);
+        guard
+    }
+
+    #[tokio::test]
+    async fn finishes_a_search_without_results_before_loading_the_model() {
+        let _api = mock_api(r#"{"meta": {"result_count": 0}}"#).await;
+        let usage = temp_path("no-results-usage.json");
+        let output = temp_path("no-results.json");
+        let args = Arguments::parse_from([
+            "happy-tweet",
+            "banana",
+            "--token",
+            "banana",
+            "--usage-file",
+            usage.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+        ]);
+        let mut pool = HashMap::new();
+        let stats = search(&args, &mut pool).await.unwrap();
+
+        // What `main` exits with `NO_RESULTS_EXIT_CODE` for
+        assert_eq!((stats.pages, stats.fetched), (1, 0));
+        assert!(!output.exists());
+        let classifier = pool.values().next().unwrap();
+        assert!(classifier.classifier.is_none());
+    }
+
+    #[test]
+    fn sorts_tweets_of_the_same_score_the_same_way() {
+        let tweet = |user: &str, id: &str, score: f64| {
+            let mut tweet = happy_tweet(id);
+            tweet.tweet.url = format!("https://twitter.com/{}/status/{}", user, id);
+            tweet.sentiment = Some(Sentiment {
+                polarity: SentimentPolarity::Positive,
+                score,
+            });
+            tweet
+        };
+        let tweets = || {
+            vec![
+                tweet("banana", "1000000000000000000", 0.9),
+                tweet("apple", "1500000000000000000", 0.9),
+                tweet("banana", "1500000000000000000", 0.9),
+                tweet("cherry", "1200000000000000000", 0.95),
+            ]
+        };
+        let now = posted_at("1600000000000000000").unwrap();
+        for order in [SortOrder::Happiest, SortOrder::Trending] {
+            let mut sorted = None;
+            for rotation in 0..4 {
+                let mut tweets = tweets();
+                tweets.rotate_left(rotation);
+                tweets.reverse();
+                order.sort(&mut tweets, now, Duration::from_secs(3_600_000));
+                let urls = tweets
+                    .into_iter()
+                    .map(|tweet| tweet.tweet.url)
+                    .collect::<Vec<String>>();
+                assert_eq!(sorted.get_or_insert_with(|| urls.clone()), &urls);
+            }
+            if order == SortOrder::Happiest {
+                assert_eq!(
+                    sorted.unwrap(),
+                    [
+                        "https://twitter.com/cherry/status/1200000000000000000",
+                        "https://twitter.com/apple/status/1500000000000000000",
+                        "https://twitter.com/banana/status/1500000000000000000",
+                        "https://twitter.com/banana/status/1000000000000000000",
+                    ]
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn truncates_texts_to_a_limit() {
+        assert_eq!(truncate_chars("banana", 6), "banana");
+        assert_eq!(truncate_chars("banana", 4), "ban…");
+        assert_eq!(truncate_chars("🍌🍌🍌", 2), "🍌…");
+        assert_eq!(truncate_chars("banana", 0), "");
+        assert_eq!(truncate_bytes("banana", 3), "ban");
+        assert_eq!(truncate_bytes("🍌🍌", 5), "🍌");
+        assert_eq!(truncate_bytes("🍌", 0), "");
+    }
+
+    #[test]
+    fn streams_with_the_model_and_text_options_of_the_arguments() {
+        let args = Arguments::parse_from([
+            "happy-tweet",
+            "stream",
+            "banana",
+            "--model",
+            "multilingual",
+            "--device",
+            "cpu",
+            "--strip-emoji",
+        ]);
+        let classifier = LazyClassifier::new(&args).unwrap();
+        assert_eq!(
+            classifier.model.unwrap().id,
+            ModelPreset::Multilingual.config(Device::Cpu).id
+        );
+        assert!(classifier.strip_emoji);
+    }
+
+    #[test]
+    fn collapses_the_whitespace_of_the_content() {
+        let normalizer = ContentNormalizer {
+            strip_zero_width: false,
+        };
+        assert_eq!(
+            normalizer.normalize("  I\tlove \n\n bananas  "),
+            "I love bananas"
+        );
+        assert_eq!(
+            normalizer.normalize("I love\u{00A0}\u{3000}bananas"),
+            "I love bananas"
+        );
+        assert_eq!(normalizer.normalize(" \n\t "), "");
+        assert_eq!(
+            normalizer.normalize("I\u{200B} love bananas"),
+            "I\u{200B} love bananas"
+        );
+    }
+
+    #[test]
+    fn strips_zero_width_characters_before_collapsing() {
+        let normalizer = ContentNormalizer {
+            strip_zero_width: true,
+        };
+        assert_eq!(
+            normalizer.normalize("I \u{200B} love\u{FEFF}  ba\u{200C}na\u{2060}nas"),
+            "I love bananas"
+        );
+        // The joiner of emoji like 👩‍💻 is kept
+        assert_eq!(
+            normalizer.normalize("👩\u{200D}💻  rocks"),
+            "👩\u{200D}💻 rocks"
+        );
+
+        let args = Arguments::parse_from(["happy-tweet", "banana"]);
+        assert!(ContentNormalizer::new(&args).is_none());
+        let args = Arguments::parse_from([
+            "happy-tweet",
+            "banana",
+            "--normalize-content",
+            "--strip-zero-width",
+        ]);
+        assert!(ContentNormalizer::new(&args).unwrap().strip_zero_width);
+    }
+
+    #[test]
+    fn strips_emoji_sequences() {
+        assert_eq!(strip_emoji("I love bananas 🍌🍌"), "I love bananas ");
+        // Joined, skin toned and flag sequences go whole
+        assert_eq!(strip_emoji("I 👩‍💻 love 👨‍👩‍👧 bananas"), "I  love  bananas");
+        assert_eq!(strip_emoji("I 👍🏽 love 👋🏿 bananas"), "I  love  bananas");
+        assert_eq!(strip_emoji("I love 🇪🇸 bananas 🏳️‍🌈"), "I love  bananas ");
+        // A joiner not after an emoji is kept
+        assert_eq!(strip_emoji("क्\u{200D}ष"), "क्\u{200D}ष");
+        // A text of only emoji is left for the model
+        assert_eq!(strip_emoji("🍌 👩‍💻 🇪🇸"), "🍌 👩‍💻 🇪🇸");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[ignore = "downloads the sentiment model"]
+    async fn classifies_texts_stripped_of_emoji() {
+        let mut classifier = classifier();
+        classifier.strip_emoji = true;
+        let texts = ["I love bananas 🍌👩‍💻🇪🇸", "👍🏽🍌"].map(String::from).to_vec();
+        let sentiments = classifier.predict(texts).await.unwrap();
+        assert_eq!(sentiments.len(), 2);
+        assert!(sentiments.iter().all(Result::is_ok));
+    }
+
+    #[test]
+    fn parses_ranges_of_hours() {
+        assert_eq!(parse_hours("9-17"), Ok((9, 17)));
+        assert_eq!(parse_hours("22-2"), Ok((22, 2)));
+        assert_eq!(parse_hours(" 22 - 24 "), Ok((22, 0)));
+        assert_eq!(parse_hours("0-24"), Ok((0, 24)));
+        for invalid in [
+            "9", "9-9", "0-0", "24-0", "9-25", "-1-5", "a-b", "9-17-20", "",
+        ] {
+            assert!(parse_hours(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn keeps_the_tweets_posted_in_a_range_of_hours() {
+        let at = |created_at: &str| TwitterApiResponseData {
+            created_at: created_at.to_string(),
+            ..api_tweet("1", "I love bananas")
+        };
+        let filter = |hours: &str, timezone: Option<&str>| {
+            let (start, end) = parse_hours(hours).unwrap();
+            HoursFilter {
+                start,
+                end,
+                timezone: timezone.map(|timezone| timezone.parse().unwrap()),
+            }
+        };
+        let keeps = |filter: &HoursFilter, created_at| filter.keep(&at(created_at));
+
+        let wrapping = filter("22-2", None);
+        assert!(keeps(&wrapping, "2022-04-20T22:00:00.000Z"));
+        assert!(keeps(&wrapping, "2022-04-20T23:59:59.000Z"));
+        assert!(keeps(&wrapping, "2022-04-21T01:59:59.000Z"));
+        assert!(!keeps(&wrapping, "2022-04-21T02:00:00.000Z"));
+        assert!(!keeps(&wrapping, "2022-04-20T21:59:59.000Z"));
+
+        let day = filter("9-17", None);
+        assert!(keeps(&day, "2022-04-20T09:00:00.000Z"));
+        assert!(!keeps(&day, "2022-04-20T17:00:00.000Z"));
+        // 16:20 UTC is 18:20 in Madrid
+        assert!(!keeps(
+            &filter("9-17", Some("Europe/Madrid")),
+            "2022-04-20T16:20:00.000Z"
+        ));
+        assert!(keeps(&filter("0-24", None), "2022-04-20T00:00:00.000Z"));
+        // Invalid dates are left for the writing to fail on
+        assert!(keeps(&day, "yesterday"));
+    }
+
+    #[test]
+    fn builds_the_query_of_the_flags() {
+        let query = QueryBuilder::new("paella")
+            .any("valencia madrid")
+            .all("rice tasty")
+            .hashtag("food")
+            .hashtag("#cooking")
+            .hashtag("food")
+            .lang("es")
+            .near("-0.37,39.47,10km")
+            .exclude(NON_ORIGINAL_KINDS[0])
+            .exclude(NON_ORIGINAL_KINDS[0]);
+        assert_eq!(
+            query.build(),
+            Ok(String::from(
+                "paella (valencia OR madrid) rice tasty (#food OR #cooking) lang:es point_radius:[-0.37 39.47 10km] -is:retweet"
+            ))
+        );
+        assert_eq!(
+            QueryBuilder::new("paella")
+                .any("valencia")
+                .hashtag("food")
+                .build(),
+            Ok(String::from("paella valencia #food"))
+        );
+
+        let args = Arguments::parse_from([
+            "happy-tweet",
+            "paella -is:reply",
+            "--hashtag",
+            "food",
+            "--near",
+            "-0.37,39.47,25mi",
+            "--originals-only",
+        ]);
+        assert_eq!(
+            QueryBuilder::from_args("paella -is:reply", &args).build(),
+            Ok(String::from(
+                "paella -is:reply #food point_radius:[-0.37 39.47 25mi] -is:retweet -is:quote"
+            ))
+        );
+    }
+
+    #[test]
+    fn checks_the_conflicts_outside_of_the_alternatives() {
+        let lang = |term: &str| {
+            QueryBuilder::new(term)
+                .any("valencia madrid")
+                .lang("en")
+                .build()
+        };
+        assert!(lang("paella lang:es").is_err());
+        assert!(lang("(paella lang:es)").is_err());
+        assert!(QueryBuilder::new("paella")
+            .all("lang:es")
+            .lang("en")
+            .build()
+            .is_err());
+        assert!(lang("paella OR lang:es").is_ok());
+        assert!(lang("paella (rice OR lang:es)").is_ok());
+        assert!(lang("paella \"lang:es\"").is_ok());
+
+        let quotes = |term: &str| {
+            QueryBuilder::new(term)
+                .any("valencia madrid")
+                .exclude(NON_ORIGINAL_KINDS[2])
+                .build()
+        };
+        assert!(quotes("paella is:quote").is_err());
+        assert!(quotes("paella (is:quote OR is:reply)").is_ok());
+        assert_eq!(
+            quotes("paella -is:quote"),
+            Ok(String::from("paella -is:quote (valencia OR madrid)"))
+        );
+    }
+
+    #[test]
+    fn limits_the_length_of_the_query() {
+        let term = "a".repeat(MAX_QUERY_LENGTH);
+        assert!(QueryBuilder::new(&term).build().is_ok());
+        let error = QueryBuilder::new(&term).lang("es").build().unwrap_err();
+        assert!(error.contains("Twitter accepts up to 512"));
+    }
+
+    #[test]
+    fn validates_the_point_of_near() {
+        for valid in ["-0.37,39.47,10km", "180,-90,25mi", "0,0,40.2km"] {
+            assert_eq!(validate_near(valid), Ok(()), "{}", valid);
+        }
+        for invalid in [
+            "-0.37,39.47",
+            "-0.37,39.47,10",
+            "-0.37,39.47,26mi",
+            "-0.37,39.47,41km",
+            "-0.37,39.47,0km",
+            "181,0,1km",
+            "0,91,1km",
+            "a,b,1km",
+        ] {
+            assert!(validate_near(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[ignore = "downloads the sentiment model"]
+    async fn gives_identical_scores_across_runs() {
+        let texts = ["I love bananas", "I hate mondays", "Bananas, I guess"];
+        let run = || async {
+            let mut classifier = classifier();
+            scores(&mut classifier, &texts)
+                .await
+                .into_iter()
+                .map(|(positive, score)| (positive, score.to_bits()))
+                .collect::<Vec<(bool, u64)>>()
+        };
+        assert_eq!(run().await, run().await);
+    }
+
+    #[test]
+    fn rescues_the_results_of_io_errors_only() {
+        // The folder of the output doesn't exist
+        let path = temp_path("missing/rescued.ndjson");
+        let tweets = [happy_tweet("1")];
+        let tweets = tweets.iter().collect::<Vec<&HappyTweet>>();
+        let error =
+            write_tweets(&tweets, &path, OutputFormat::Ndjson, &write_options()).unwrap_err();
+        let rescued = rescue_output(&path, error, || ndjson_lines(&tweets), "ndjson", false);
+        let message = rescued.to_string();
+        assert!(
+            message.contains("The results were written to"),
+            "{}",
+            message
+        );
+        let fallback = message
+            .rsplit('`')
+            .nth(1)
+            .map(std::path::PathBuf::from)
+            .unwrap();
+        assert_eq!(read_ndjson_file(&fallback).unwrap().len(), 1);
+        std::fs::remove_file(fallback).unwrap();
+
+        // A refused output is left to fix and run again
+        let path = temp_path("refused.json");
+        write(&path, &["1"], OutputFormat::Json).unwrap();
+        let error =
+            write_tweets(&tweets, &path, OutputFormat::Ndjson, &write_options()).unwrap_err();
+        let reason = error.to_string();
+        let refused = rescue_output(&path, error, || ndjson_lines(&tweets), "ndjson", false);
+        assert_eq!(refused.to_string(), reason);
+        assert!(is_io_error(&std::io::Error::from(
+            std::io::ErrorKind::StorageFull
+        )));
+    }
+
+    #[tokio::test]
+    async fn prunes_with_the_limits_and_indent_of_the_arguments() {
+        let _api = mock_api(
+            r#"{"errors": [{"resource_id": "2", "type": "https://api.twitter.com/2/problems/resource-not-found"}]}"#,
+        )
+        .await;
+        let path = temp_path("prune.json");
+        write(&path, &["1", "2"], OutputFormat::Json).unwrap();
+        let args = Arguments::parse_from([
+            "happy-tweet",
+            "prune",
+            path.to_str().unwrap(),
+            "--rate-limit",
+            "10",
+            "--min-interval",
+            "5",
+            "--retry-budget",
+            "1m",
+            "--indent",
+            "4",
+        ]);
+        let limiter = RateLimiter::from_args(&args).unwrap();
+        assert_eq!(limiter.bucket.lock().unwrap().capacity, 10.0);
+        assert_eq!(
+            limiter.bucket.lock().unwrap().min_interval,
+            Duration::from_millis(5)
+        );
+        assert_eq!(limiter.retry_budget.limit, Some(Duration::from_secs(60)));
+
+        let client = twitter_client(&args.header, &args.user_agent).unwrap();
+        prune(&path, &client, "banana", &args).await.unwrap();
+        let tweets = read_output_file(&path).unwrap().into_tweets();
+        assert_eq!(tweets.iter().map(HappyTweet::id).collect::<Vec<_>>(), ["1"]);
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(contents.starts_with("[\n    {"), "{}", contents);
+    }
+
+    #[tokio::test]
+    async fn refuses_an_envelope_for_ndjson_before_searching() {
+        let json = temp_path("enveloped.json");
+        let ndjson = temp_path("enveloped.ndjson");
+        let args = Arguments::parse_from([
+            "happy-tweet",
+            "banana",
+            "-o",
+            json.to_str().unwrap(),
+            "-o",
+            ndjson.to_str().unwrap(),
+            "--envelope",
+        ]);
+        let error = search(&args, &mut HashMap::new()).await.unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("can't be wrapped in an envelope"),
+            "{}",
+            error
+        );
+        assert!(!json.exists());
+    }
+
+    #[test]
+    fn merges_outputs_keeping_each_tweet_once_with_all_its_terms() {
+        let tweets = |ids: &[&str], term: &str| {
+            ids.iter()
+                .map(|id| {
+                    let mut tweet = happy_tweet(id);
+                    tweet.tweet.matched_terms = vec![term.to_string()];
+                    tweet
+                })
+                .collect::<Vec<HappyTweet>>()
+        };
+        let first = temp_path("merge-first.ndjson");
+        let second = temp_path("merge-second.ndjson");
+        let merged = temp_path("merged.json");
+        for (path, tweets) in [
+            (&first, tweets(&["1", "2"], "banana")),
+            (&second, tweets(&["2", "3", "2"], "apple")),
+        ] {
+            let tweets = tweets.iter().collect::<Vec<&HappyTweet>>();
+            let options = WriteOptions {
+                overwrite: true,
+                ..write_options()
+            };
+            write_tweets(&tweets, path, OutputFormat::Ndjson, &options).unwrap();
+        }
+        merge(
+            &[first, second],
+            &merged,
+            OutputFormat::Json,
+            None,
+            false,
+            Locale::C,
+        )
+        .unwrap();
+        let tweets = read_output_file(&merged).unwrap().into_tweets();
+        let terms = tweets
+            .iter()
+            .map(|tweet| (tweet.id(), tweet.tweet.matched_terms.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            terms,
+            [
+                ("3", vec![String::from("apple")]),
+                ("2", vec![String::from("banana"), String::from("apple")]),
+                ("1", vec![String::from("banana")]),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn drops_collapsed_originals_without_their_author() {
+        let _api = mock_api(
+            r#"{
+                "data": [
+                    {"id": "1", "text": "RT I love bananas", "created_at": "2022-04-20T16:20:00.000Z", "author_id": "10",
+                     "referenced_tweets": [{"type": "retweeted", "id": "2"}]},
+                    {"id": "3", "text": "I love apples", "created_at": "2022-04-20T16:21:00.000Z", "author_id": "10"}
+                ],
+                "includes": {
+                    "users": [{"id": "10", "username": "fan", "name": "Fan", "profile_image_url": "https://pbs.twimg.com/fan.png"}],
+                    "tweets": [{"id": "2", "text": "I love bananas", "created_at": "2022-04-20T16:00:00.000Z", "author_id": "20"}]
+                },
+                "meta": {"result_count": 2}
+            }"#,
+        )
+        .await;
+        let usage = temp_path("collapsed-usage.json");
+        let output = temp_path("collapsed.json");
+        let args = Arguments::parse_from([
+            "happy-tweet",
+            "banana",
+            "--token",
+            "banana",
+            "--usage-file",
+            usage.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--collapse-retweets",
+            "--no-sentiment",
+        ]);
+        search(&args, &mut HashMap::new()).await.unwrap();
+        let tweets = read_output_file(&output).unwrap().into_tweets();
+        assert_eq!(tweets.iter().map(HappyTweet::id).collect::<Vec<_>>(), ["3"]);
+    }
+
+    #[test]
+    fn saves_the_fingerprints_atomically() {
+        let output = temp_path("fingerprinted.json");
+        let mut fingerprints = Fingerprints::load(&output, None).unwrap();
+        fingerprints.insert(
+            "https://twitter.com/banana/status/2",
+            "I love  https://t.co/a bananas",
+        );
+        fingerprints.insert("https://twitter.com/banana/status/1", "I love apples");
+        fingerprints.save().unwrap();
+        let path = std::path::PathBuf::from(format!("{}.fingerprints", output.display()));
+        assert!(!std::path::PathBuf::from(format!("{}.tmp", path.display())).exists());
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(contents.starts_with("https://twitter.com/banana/status/1\t"));
+        let fingerprints = Fingerprints::load(&output, None).unwrap();
+        assert!(fingerprints.contains(&fingerprint("i LOVE bananas https://t.co/b")));
+        assert!(!fingerprints.contains(&fingerprint("I love cherries")));
+    }
+
+    #[test]
+    fn appends_streamed_tweets_once() {
+        let path = temp_path("stream.ndjson");
+        write(&path, &["1"], OutputFormat::Ndjson).unwrap();
+        let mut output = StreamOutput::open(&path, OutputEncoding::Utf8).unwrap();
+        for id in ["2", "1", "3", "2"] {
+            output.append(&happy_tweet(id)).unwrap();
+            // Every tweet can be read as soon as it is appended
+            assert!(std::fs::read_to_string(&path)
+                .unwrap()
+                .contains(&format!("status/{}", id)));
+        }
+        let ids = read_ndjson_file(&path)
+            .unwrap()
+            .iter()
+            .map(|tweet| tweet.id().to_string())
+            .collect::<Vec<String>>();
+        assert_eq!(ids, ["1", "2", "3"]);
+
+        // In the `--output-encoding` of the arguments
+        let path = temp_path("stream-bom.ndjson");
+        let args = Arguments::parse_from([
+            "happy-tweet",
+            "stream",
+            "banana",
+            "--output-encoding",
+            "utf8-bom",
+        ]);
+        let mut output = StreamOutput::open(&path, args.output_encoding).unwrap();
+        output.append(&happy_tweet("1")).unwrap();
+        assert!(std::fs::read(&path).unwrap().starts_with(UTF8_BOM));
+    }
+
+    #[tokio::test]
+    async fn refuses_to_stream_to_outputs_rewritten_whole() {
+        let args = Arguments::parse_from(["happy-tweet", "stream", "banana"]);
+        for (name, format) in [
+            ("stream.md", OutputFormat::Markdown),
+            ("stream.json", OutputFormat::Json),
+            ("stream.ndjson.gz", OutputFormat::Ndjson),
+        ] {
+            let path = temp_path(name);
+            let error = stream(
+                "banana",
+                &path,
+                format,
+                SentimentFilter::All,
+                None,
+                "banana",
+                &args,
+            )
+            .await
+            .unwrap_err();
+            assert!(error.to_string().contains("only plain NDJSON"), "{}", error);
+            assert!(!path.exists());
+        }
+    }
+
+    #[tokio::test]
+    async fn filters_the_authors_of_collapsed_originals() {
+        for (flag, expected) in [("--exclude-authors", "3"), ("--only-authors", "2")] {
+            let _api = mock_api(
+                r#"{
+                    "data": [
+                        {"id": "1", "text": "RT I love bananas", "created_at": "2022-04-20T16:20:00.000Z", "author_id": "10",
+                         "referenced_tweets": [{"type": "retweeted", "id": "2"}]},
+                        {"id": "3", "text": "I love apples", "created_at": "2022-04-20T16:21:00.000Z", "author_id": "10"}
+                    ],
+                    "includes": {
+                        "users": [
+                            {"id": "10", "username": "fan", "name": "Fan", "profile_image_url": "https://pbs.twimg.com/fan.png"},
+                            {"id": "20", "username": "brand", "name": "Brand", "profile_image_url": "https://pbs.twimg.com/brand.png"}
+                        ],
+                        "tweets": [{"id": "2", "text": "I love bananas", "created_at": "2022-04-20T16:00:00.000Z", "author_id": "20"}]
+                    },
+                    "meta": {"result_count": 2}
+                }"#,
+            )
+            .await;
+            let usage = temp_path("authors-collapsed-usage.json");
+            let output = temp_path("authors-collapsed.json");
+            let args = Arguments::parse_from([
+                "happy-tweet",
+                "banana",
+                "--token",
+                "banana",
+                "--usage-file",
+                usage.to_str().unwrap(),
+                "-o",
+                output.to_str().unwrap(),
+                "--collapse-retweets",
+                flag,
+                "brand",
+                "--no-sentiment",
+            ]);
+            search(&args, &mut HashMap::new()).await.unwrap();
+            let tweets = read_output_file(&output).unwrap().into_tweets();
+            assert_eq!(
+                tweets.iter().map(HappyTweet::id).collect::<Vec<_>>(),
+                [expected]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn checks_the_profiles_of_collapsed_originals() {
+        for flag in ["--require-avatar", "--require-bio"] {
+            let _api = mock_api(
+                r#"{
+                    "data": [
+                        {"id": "1", "text": "RT I love bananas", "created_at": "2022-04-20T16:20:00.000Z", "author_id": "10",
+                         "referenced_tweets": [{"type": "retweeted", "id": "2"}]},
+                        {"id": "3", "text": "I love apples", "created_at": "2022-04-20T16:21:00.000Z", "author_id": "10"}
+                    ],
+                    "includes": {
+                        "users": [
+                            {"id": "10", "username": "fan", "name": "Fan", "profile_image_url": "https://pbs.twimg.com/fan.png", "description": "Bananas all day"},
+                            {"id": "20", "username": "egg", "name": "Egg", "profile_image_url": "https://abs.twimg.com/sticky/default_profile_images/default_profile_normal.png", "description": ""}
+                        ],
+                        "tweets": [{"id": "2", "text": "I love bananas", "created_at": "2022-04-20T16:00:00.000Z", "author_id": "20"}]
+                    },
+                    "meta": {"result_count": 2}
+                }"#,
+            )
+            .await;
+            let usage = temp_path("profiles-collapsed-usage.json");
+            let output = temp_path("profiles-collapsed.json");
+            let args = Arguments::parse_from([
+                "happy-tweet",
+                "banana",
+                "--token",
+                "banana",
+                "--usage-file",
+                usage.to_str().unwrap(),
+                "-o",
+                output.to_str().unwrap(),
+                "--collapse-retweets",
+                flag,
+                "--no-sentiment",
+            ]);
+            search(&args, &mut HashMap::new()).await.unwrap();
+            let tweets = read_output_file(&output).unwrap().into_tweets();
+            assert_eq!(tweets.iter().map(HappyTweet::id).collect::<Vec<_>>(), ["3"]);
+        }
     }
 }